@@ -0,0 +1,3 @@
+DROP TABLE idempotency;
+
+DROP TYPE header_pair;
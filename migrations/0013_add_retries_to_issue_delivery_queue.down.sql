@@ -0,0 +1,3 @@
+ALTER TABLE issue_delivery_queue
+    DROP COLUMN n_retries,
+    DROP COLUMN execute_after;
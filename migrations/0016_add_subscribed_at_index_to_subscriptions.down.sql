@@ -0,0 +1 @@
+DROP INDEX subscriptions_subscribed_at_id_idx;
@@ -0,0 +1,4 @@
+ALTER TABLE subscriptions
+    ALTER COLUMN status TYPE TEXT USING status::TEXT;
+
+DROP TYPE subscription_status;
@@ -0,0 +1,5 @@
+CREATE TYPE subscription_status AS ENUM ('pending', 'confirmed', 'unsubscribed', 'bounced');
+
+-- Fails on any row holding a value outside the enum, which is the point
+ALTER TABLE subscriptions
+    ALTER COLUMN status TYPE subscription_status USING status::subscription_status;
@@ -0,0 +1,16 @@
+CREATE TABLE subscription_status_history(
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    -- NULL for the status a subscription was created with
+    from_status subscription_status NULL,
+    to_status subscription_status NOT NULL,
+    changed_at timestamptz NOT NULL
+);
+
+CREATE INDEX subscription_status_history_subscriber_id_idx
+    ON subscription_status_history (subscriber_id, changed_at);
+
+-- Seed the history with the current status of existing subscriptions
+INSERT INTO subscription_status_history (subscriber_id, from_status, to_status, changed_at)
+SELECT id, NULL, status, subscribed_at
+FROM subscriptions;
@@ -0,0 +1,2 @@
+-- Drops subscription_tokens_created_at_idx along with the column
+ALTER TABLE subscription_tokens DROP COLUMN created_at;
@@ -0,0 +1,3 @@
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+
+CREATE INDEX subscription_tokens_created_at_idx ON subscription_tokens (created_at);
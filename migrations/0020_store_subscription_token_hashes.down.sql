@@ -0,0 +1,7 @@
+-- Hashes cannot be turned back into the tokens that were emailed; pending
+-- subscribers get a fresh link by signing up again
+DELETE FROM subscription_tokens;
+
+ALTER TABLE subscription_tokens
+    ALTER COLUMN subscription_token_hash TYPE TEXT USING encode(subscription_token_hash, 'escape');
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token_hash TO subscription_token;
@@ -0,0 +1,7 @@
+-- Outstanding tokens were stored in clear text and cannot be hashed with
+-- SHA3 in SQL; pending subscribers get a fresh link by signing up again
+DELETE FROM subscription_tokens;
+
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO subscription_token_hash;
+ALTER TABLE subscription_tokens
+    ALTER COLUMN subscription_token_hash TYPE BYTEA USING subscription_token_hash::BYTEA;
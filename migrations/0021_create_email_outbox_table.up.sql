@@ -0,0 +1,12 @@
+CREATE TABLE email_outbox(
+    email_id uuid PRIMARY KEY,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    n_retries INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL
+);
+
+CREATE INDEX email_outbox_execute_after_idx ON email_outbox (execute_after);
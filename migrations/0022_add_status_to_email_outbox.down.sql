@@ -0,0 +1,12 @@
+DROP INDEX email_outbox_unsent_idx;
+CREATE INDEX email_outbox_execute_after_idx ON email_outbox (execute_after);
+
+ALTER TABLE email_outbox
+    DROP COLUMN status,
+    DROP COLUMN sender,
+    DROP COLUMN reply_to,
+    DROP COLUMN headers,
+    DROP COLUMN last_error,
+    DROP COLUMN sent_at;
+
+DROP TYPE outbox_status;
@@ -0,0 +1,14 @@
+CREATE TYPE outbox_status AS ENUM ('pending', 'retrying', 'sent', 'failed');
+
+ALTER TABLE email_outbox
+    ADD COLUMN status outbox_status NOT NULL DEFAULT 'pending',
+    ADD COLUMN sender TEXT NOT NULL DEFAULT '',
+    ADD COLUMN reply_to TEXT NOT NULL DEFAULT '',
+    ADD COLUMN headers JSONB NOT NULL DEFAULT '[]',
+    ADD COLUMN last_error TEXT NULL,
+    ADD COLUMN sent_at timestamptz NULL;
+
+-- The relay only ever looks at emails that still need sending
+DROP INDEX email_outbox_execute_after_idx;
+CREATE INDEX email_outbox_unsent_idx ON email_outbox (execute_after)
+    WHERE status IN ('pending', 'retrying');
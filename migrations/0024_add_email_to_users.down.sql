@@ -0,0 +1,2 @@
+-- Drops users_email_idx along with the column
+ALTER TABLE users DROP COLUMN email;
@@ -0,0 +1,8 @@
+CREATE TABLE password_reset_tokens(
+    token_hash BYTEA NOT NULL,
+    PRIMARY KEY (token_hash),
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
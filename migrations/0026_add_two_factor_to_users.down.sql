@@ -0,0 +1,5 @@
+DROP TABLE totp_recovery_codes;
+
+ALTER TABLE users
+    DROP COLUMN totp_secret,
+    DROP COLUMN totp_last_used_step;
@@ -0,0 +1,10 @@
+ALTER TABLE users
+    -- AES-256-GCM encrypted with two_factor.encryption_key
+    ADD COLUMN totp_secret BYTEA NULL,
+    ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash BYTEA NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
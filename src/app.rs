@@ -1,17 +1,59 @@
-use crate::{config::Config, email::EmailService};
-use sqlx::postgres::PgPoolOptions;
-use std::{net::TcpListener, sync::Arc};
+use crate::{
+    auth::reject_anonymous_users,
+    config::{Config, ConfirmationConfig, DeliveryConfig, SessionConfig},
+    delivery,
+    email::EmailService,
+    migrations, outbox,
+    routes::Readiness,
+    session::PgSessionStore,
+    subscription_tokens,
+    two_factor::SecretCipher,
+    unsubscribe::UnsubscribeLinks,
+};
+use actix_session::{config::CookieContentSecurity, SessionMiddleware};
+use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
+use actix_web_lab::middleware::from_fn;
+use secrecy::ExposeSecret;
+use std::{future::Future, net::TcpListener, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-use actix_web::{dev::Server, middleware::Logger, web, App, HttpServer};
+use actix_web::{
+    cookie::{Key, SameSite},
+    dev::Server,
+    middleware::Logger,
+    web, App, HttpServer,
+};
 use sqlx::{Pool, Postgres};
 
 use crate::routes::{
-    confirm, health_check, home, login, login_form, publish_newsletter, subscribe,
+    admin_dashboard, change_password, change_password_form, confirm, disable_two_factor,
+    enable_two_factor, forgot_password, forgot_password_form, health_check, home,
+    list_dead_letters, list_subscribers, log_out, login, login_form, login_two_factor,
+    login_two_factor_form, newsletter_form, publish_newsletter, publish_newsletter_form,
+    replay_dead_letters, reset_password, reset_password_form, subscribe, subscribers_page,
+    two_factor_page, unsubscribe, unsubscribe_form,
 };
 
+/// Public URL of the application, for building links in emails sent from
+/// request handlers.
+#[derive(Clone, Debug)]
+pub struct ApplicationBaseUrl(pub String);
+
 pub struct Application {
     port: u16,
     server: Server,
+    pool: Pool<Postgres>,
+    /// Whether the migrations still have to be applied when the application
+    /// starts running, because the database may not be up yet.
+    migrate_when_reachable: bool,
+    readiness: web::Data<Readiness>,
+    workers: Workers,
+    /// Cancelled to ask the background workers to stop.
+    shutdown: CancellationToken,
+    shutdown_timeout: Duration,
 }
 
 impl Application {
@@ -20,46 +62,151 @@ impl Application {
         addr: String,
         email_service: Arc<dyn EmailService + Send + Sync>,
     ) -> Result<Self, String> {
-        let pool = PgPoolOptions::new()
-            .connect(&config.db_config.url)
-            .await
-            .map_err(|e| format!("Error connecting to DB: {}", e))?;
-        sqlx::migrate!()
-            .run(&pool)
-            .await
-            .map_err(|e| format!("Error migrating db {}", e))?;
+        let db_config = &config.db_config;
+        let pool = if db_config.connect_lazily {
+            db_config
+                .pool_options()
+                .connect_lazy_with(db_config.connect_options())
+        } else {
+            let pool = db_config
+                .pool_options()
+                .connect_with(db_config.connect_options())
+                .await
+                .map_err(|e| format!("Error connecting to DB: {}", e))?;
+            if db_config.migrate_on_startup {
+                migrations::run(&pool).await?;
+            }
+            pool
+        };
 
         let listener =
             TcpListener::bind(addr.clone()).map_err(|e| format!("Error binding {} {}", addr, e))?;
         let port = listener.local_addr().unwrap().port();
-        let server = Self::run(listener, pool, email_service)?;
 
-        Ok(Self { port, server })
+        let unsubscribe_links = UnsubscribeLinks::new(
+            config.base_url.clone(),
+            config.session_config.hmac_secret.clone(),
+        );
+        let workers = Workers {
+            delivery_config: config.delivery_config.clone(),
+            confirmation_config: config.confirmation_config.clone(),
+            pool: pool.clone(),
+            email_service,
+            unsubscribe_links: unsubscribe_links.clone(),
+        };
+        let migrate_when_reachable = db_config.connect_lazily && db_config.migrate_on_startup;
+        let readiness = web::Data::new(Readiness::default());
+        if !migrate_when_reachable {
+            readiness.mark_ready();
+        }
+        let server = Self::run(
+            listener,
+            pool.clone(),
+            unsubscribe_links,
+            readiness.clone(),
+            config,
+        )?;
+
+        Ok(Self {
+            port,
+            server,
+            pool,
+            migrate_when_reachable,
+            readiness,
+            workers,
+            shutdown: CancellationToken::new(),
+            shutdown_timeout: config.shutdown_timeout,
+        })
     }
 
     fn run(
         listener: TcpListener,
         pool: Pool<Postgres>,
-        email_service: Arc<dyn EmailService + Send + Sync>,
+        unsubscribe_links: UnsubscribeLinks,
+        readiness: web::Data<Readiness>,
+        config: &Config,
     ) -> Result<Server, String> {
+        let secret_key = session_key(&config.session_config)?;
+        let secure_cookie = config.session_config.secure_cookie;
+        let session_store = PgSessionStore::new(pool.clone());
+        let message_framework = FlashMessagesFramework::builder(
+            CookieMessageStore::builder(secret_key.clone()).build(),
+        )
+        .build();
         let pool = web::Data::new(pool);
-        let email_service = web::Data::new(email_service);
+        let base_url = web::Data::new(ApplicationBaseUrl(config.base_url.clone()));
+        let unsubscribe_links = web::Data::new(unsubscribe_links);
+        let confirmation_config = web::Data::new(config.confirmation_config.clone());
+        let password_hash_config = web::Data::new(config.password_hash_config.clone());
+        let password_policy_config = web::Data::new(config.password_policy_config.clone());
+        let password_reset_config = web::Data::new(config.password_reset_config.clone());
+        let secret_cipher = web::Data::new(SecretCipher::new(&config.two_factor_config)?);
         let server = HttpServer::new(move || {
             let pool = pool.clone();
-            let email_service = email_service.clone();
+            let base_url = base_url.clone();
+            let unsubscribe_links = unsubscribe_links.clone();
+            let confirmation_config = confirmation_config.clone();
+            let password_hash_config = password_hash_config.clone();
+            let password_policy_config = password_policy_config.clone();
+            let password_reset_config = password_reset_config.clone();
+            let secret_cipher = secret_cipher.clone();
+            let readiness = readiness.clone();
+            let session_middleware =
+                SessionMiddleware::builder(session_store.clone(), secret_key.clone())
+                    .cookie_content_security(CookieContentSecurity::Signed)
+                    .cookie_http_only(true)
+                    .cookie_same_site(SameSite::Strict)
+                    .cookie_secure(secure_cookie)
+                    .build();
 
             App::new()
+                .wrap(message_framework.clone())
+                .wrap(session_middleware)
                 .wrap(Logger::default())
                 .route("/health_check", web::get().to(health_check))
                 .route("/subscriptions", web::post().to(subscribe))
                 .route("/confirm", web::get().to(confirm))
+                .route("/unsubscribe", web::get().to(unsubscribe_form))
+                .route("/unsubscribe", web::post().to(unsubscribe))
                 .route("/newsletter", web::post().to(publish_newsletter))
+                .service(
+                    web::scope("/admin")
+                        .wrap(from_fn(reject_anonymous_users))
+                        .route("/dashboard", web::get().to(admin_dashboard))
+                        .route("/newsletter", web::get().to(newsletter_form))
+                        .route("/newsletter", web::post().to(publish_newsletter_form))
+                        .route("/subscribers", web::get().to(subscribers_page))
+                        .route("/api/subscribers", web::get().to(list_subscribers))
+                        .route("/dead_letters", web::get().to(list_dead_letters))
+                        .route("/dead_letters/replay", web::post().to(replay_dead_letters))
+                        .route("/password", web::get().to(change_password_form))
+                        .route("/password", web::post().to(change_password))
+                        .route("/two_factor", web::get().to(two_factor_page))
+                        .route("/two_factor/enable", web::post().to(enable_two_factor))
+                        .route("/two_factor/disable", web::post().to(disable_two_factor))
+                        .route("/logout", web::post().to(log_out)),
+                )
                 .route("/login", web::get().to(login_form))
                 .route("/login", web::post().to(login))
+                .route("/login/two_factor", web::get().to(login_two_factor_form))
+                .route("/login/two_factor", web::post().to(login_two_factor))
+                .route("/password/forgot", web::get().to(forgot_password_form))
+                .route("/password/forgot", web::post().to(forgot_password))
+                .route("/password/reset", web::get().to(reset_password_form))
+                .route("/password/reset", web::post().to(reset_password))
                 .route("/", web::get().to(home))
                 .app_data(pool)
-                .app_data(email_service)
+                .app_data(base_url)
+                .app_data(unsubscribe_links)
+                .app_data(confirmation_config)
+                .app_data(password_hash_config)
+                .app_data(password_policy_config)
+                .app_data(password_reset_config)
+                .app_data(secret_cipher)
+                .app_data(readiness)
         })
+        .disable_signals()
+        .shutdown_timeout(config.shutdown_timeout.as_secs())
         .listen(listener)
         .map_err(|e| format!("Error listening {}", e))?
         .run();
@@ -70,7 +217,127 @@ impl Application {
         self.port
     }
 
+    /// Serves until SIGTERM or SIGINT, then shuts down gracefully.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// Serves until `shutdown` completes, then stops accepting connections
+    /// and gives in-flight requests and emails being sent up to the
+    /// configured shutdown timeout to finish before closing the pool.
+    /// Anything still running at the deadline is cut off.
+    ///
+    /// The background workers start, and the health check passes, once
+    /// pending migrations are applied. Failing to apply them stops the
+    /// application with an error.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> Result<(), std::io::Error> {
+        let server_handle = self.server.handle();
+        let mut server = tokio::spawn(self.server);
+        let mut workers = Vec::new();
+
+        let startup = async {
+            if self.migrate_when_reachable {
+                migrations::run_when_reachable(&self.pool, &self.shutdown).await?;
+                info!("Migrated db");
+                self.readiness.mark_ready();
+            }
+            workers = self.workers.spawn(&self.shutdown);
+            std::future::pending::<Result<(), String>>().await
+        };
+
+        let (result, deadline) = tokio::select! {
+            result = &mut server => (result, Instant::now() + self.shutdown_timeout),
+            _ = shutdown => {
+                info!("Shutting down, waiting up to {:?} for in-flight work", self.shutdown_timeout);
+                let deadline = Instant::now() + self.shutdown_timeout;
+                self.shutdown.cancel();
+                server_handle.stop(true).await;
+                (server.await, deadline)
+            }
+            Err(e) = startup => {
+                error!("Stopping: {}", e);
+                self.shutdown.cancel();
+                server_handle.stop(false).await;
+                let _ = server.await;
+                (Ok(Err(std::io::Error::other(e))), Instant::now())
+            }
+        };
+        self.shutdown.cancel();
+
+        for mut worker in workers {
+            if tokio::time::timeout_at(deadline, &mut worker)
+                .await
+                .is_err()
+            {
+                warn!("Shutdown timeout reached, aborting background worker");
+                worker.abort();
+            }
+        }
+        self.pool.close().await;
+        info!("Shut down");
+
+        result.unwrap_or_else(|e| Err(std::io::Error::other(e)))
+    }
+}
+
+/// Completes on SIGTERM or SIGINT.
+async fn shutdown_signal() {
+    let interrupt = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+fn session_key(session_config: &SessionConfig) -> Result<Key, String> {
+    let secret = session_config.hmac_secret.expose_secret().as_bytes();
+    if secret.len() < 64 {
+        return Err("HMAC_SECRET must be at least 64 bytes long".into());
+    }
+    Ok(Key::from(secret))
+}
+
+/// What the background workers need, kept until the application runs.
+struct Workers {
+    delivery_config: DeliveryConfig,
+    confirmation_config: ConfirmationConfig,
+    pool: Pool<Postgres>,
+    email_service: Arc<dyn EmailService + Send + Sync>,
+    unsubscribe_links: UnsubscribeLinks,
+}
+
+impl Workers {
+    /// Starts the delivery workers, the outbox relay and the confirmation
+    /// token cleanup, all stopping once `shutdown` is cancelled.
+    fn spawn(&self, shutdown: &CancellationToken) -> Vec<JoinHandle<()>> {
+        let mut workers = delivery::spawn_workers(
+            &self.delivery_config,
+            self.pool.clone(),
+            self.email_service.clone(),
+            self.unsubscribe_links.clone(),
+            shutdown.clone(),
+        );
+        workers.push(outbox::spawn_relay(
+            &self.delivery_config,
+            self.pool.clone(),
+            self.email_service.clone(),
+            shutdown.clone(),
+        ));
+        workers.push(subscription_tokens::spawn_cleanup(
+            &self.confirmation_config,
+            self.pool.clone(),
+            shutdown.clone(),
+        ));
+        workers
     }
 }
@@ -1,34 +1,45 @@
 //! src/auth.rs
 
 use std::fmt::{Display, Error, Formatter};
+use std::ops::Deref;
 
 use actix_web::{
-    http::header::{HeaderMap, LOCATION},
-    HttpResponse, ResponseError,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{HeaderMap, LOCATION, WWW_AUTHENTICATE},
+    FromRequest, HttpMessage, HttpResponse, ResponseError,
 };
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use actix_web_lab::middleware::Next;
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use base64::Engine;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{Pool, Postgres};
 use tokio::task;
-use tracing::{error, Instrument};
+use tracing::{error, info, Instrument};
 use uuid::Uuid;
 
+use crate::config::PasswordHashConfig;
+use crate::session::TypedSession;
+use crate::two_factor;
+
 #[derive(Debug)]
 pub enum AuthError {
     InvalidCredentials,
+    /// The credentials are right, but the user has a second factor, which
+    /// Basic authentication has no way to carry.
+    SecondFactorRequired,
     UnexpectedError(String),
 }
 
 impl ResponseError for AuthError {
     fn error_response(&self) -> HttpResponse {
         match self {
-            AuthError::InvalidCredentials => {
-                let encoded_error = urlencoding::Encoded::new("Invalid credentials");
-                HttpResponse::Unauthorized()
-                    .insert_header((LOCATION, format!("/login?error={}", encoded_error)))
-                    .finish()
-            }
+            AuthError::InvalidCredentials => HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, r#"Basic realm="publish""#))
+                .finish(),
+            AuthError::SecondFactorRequired => HttpResponse::Forbidden().json(self.to_string()),
             AuthError::UnexpectedError(ref message) => {
                 HttpResponse::InternalServerError().json(message)
             }
@@ -40,6 +51,10 @@ impl Display for AuthError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
             AuthError::InvalidCredentials => write!(f, "Invalid credentials"),
+            AuthError::SecondFactorRequired => write!(
+                f,
+                "Basic authentication is not accepted for users with two-factor authentication"
+            ),
             AuthError::UnexpectedError(e) => write!(f, "Unexpected error: {}", e),
         }
     }
@@ -76,27 +91,63 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, String> {
     Ok(Credentials { username, password })
 }
 
+/// Authenticates an API caller with Basic credentials.
+///
+/// Users with a second factor are refused: the password alone must not be
+/// enough to act on their behalf.
 pub async fn validate_request(
     request: actix_web::HttpRequest,
     pool: &Pool<Postgres>,
+    password_hash_config: &PasswordHashConfig,
 ) -> Result<Uuid, AuthError> {
     let credentials = basic_authentication(request.headers()).map_err(|e| {
         error!(e);
         AuthError::InvalidCredentials
     })?;
 
-    validate_credentials(credentials, pool).await
+    let user_id = validate_credentials(credentials, pool, password_hash_config).await?;
+    if two_factor::is_enabled(pool, user_id)
+        .await
+        .map_err(AuthError::UnexpectedError)?
+    {
+        return Err(AuthError::SecondFactorRequired);
+    }
+
+    Ok(user_id)
+}
+
+/// Salt and output of the hash verified instead of a real one when the
+/// username is unknown, so that unknown and known usernames take the same
+/// time to reject. No password matches it.
+const DUMMY_SALT_AND_OUTPUT: &str =
+    "qubgfm5sHq6eahgSfRKgpg$V/UNWaPJtNp9v0BLWnZfl2bWJJOEdT1rFHEqm4NM8mg";
+
+/// The dummy hash with the configured parameters, so that verifying it costs
+/// as much as verifying an up to date hash.
+fn dummy_password_hash(config: &PasswordHashConfig) -> String {
+    format!(
+        "$argon2id$v=19$m={},t={},p={}${}",
+        config.memory_kib, config.iterations, config.parallelism, DUMMY_SALT_AND_OUTPUT
+    )
 }
 
+/// Checks `credentials` against the stored password hash and returns the
+/// user's id.
+///
+/// A hash computed with other parameters than `password_hash_config` is
+/// replaced in the background once the password is known to be right.
 pub async fn validate_credentials(
     credentials: Credentials,
     pool: &Pool<Postgres>,
+    password_hash_config: &PasswordHashConfig,
 ) -> Result<Uuid, AuthError> {
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
     let user = sqlx::query!(
         r#"
-        SELECT id, password_hash FROM users WHERE username = $1
+        SELECT id, password_hash, disabled_at IS NOT NULL AS "disabled!"
+        FROM users
+        WHERE username = $1
         "#,
         credentials.username,
     )
@@ -106,28 +157,227 @@ pub async fn validate_credentials(
     .map_err(|e| {
         error!("{}", e);
         AuthError::InvalidCredentials
-    })?
-    .ok_or(AuthError::InvalidCredentials)?;
+    })?;
+
+    // Unknown and disabled users are verified like everyone else rather than
+    // rejected straight away, which would let response times reveal valid
+    // usernames.
+    let (user_id, expected_password_hash) = match user {
+        Some(user) => (Some(user.id).filter(|_| !user.disabled), user.password_hash),
+        None => (None, dummy_password_hash(password_hash_config)),
+    };
 
+    let password = credentials.password;
     let handle = task::spawn_blocking(move || {
-        let argon2 = Argon2::default();
+        verify_password_hash(&expected_password_hash, &password)
+            .map(|_| (expected_password_hash, password))
+    });
 
-        let parsed_hash =
-            PasswordHash::new(&user.password_hash).map_err(|_| AuthError::InvalidCredentials)?;
+    let (password_hash, password) = handle
+        .await
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))??;
+    let user_id = user_id.ok_or(AuthError::InvalidCredentials)?;
+
+    if needs_rehash(&password_hash, password_hash_config) {
+        spawn_rehash(
+            pool.clone(),
+            user_id,
+            password_hash,
+            password,
+            password_hash_config.clone(),
+        );
+    }
+
+    Ok(user_id)
+}
 
-        argon2
-            .verify_password(
-                credentials.password.expose_secret().as_bytes(),
-                &parsed_hash,
+fn verify_password_hash(
+    expected_password_hash: &str,
+    password: &Secret<String>,
+) -> Result<(), AuthError> {
+    let parsed_hash =
+        PasswordHash::new(expected_password_hash).map_err(|_| AuthError::InvalidCredentials)?;
+
+    Argon2::default()
+        .verify_password(password.expose_secret().as_bytes(), &parsed_hash)
+        .map_err(|_| AuthError::InvalidCredentials)
+}
+
+/// Hashes `password` with a fresh salt and the configured Argon2id
+/// parameters, returning the PHC string to store.
+pub fn compute_password_hash(
+    password: &Secret<String>,
+    config: &PasswordHashConfig,
+) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, config.params()?)
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| format!("Error hashing password: {}", e))?;
+
+    Ok(password_hash.to_string())
+}
+
+/// Whether `password_hash` was computed with another algorithm, version or
+/// parameters than the configured ones.
+fn needs_rehash(password_hash: &str, config: &PasswordHashConfig) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    let Ok(params) = Params::try_from(&parsed_hash) else {
+        return true;
+    };
+
+    parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != config.memory_kib
+        || params.t_cost() != config.iterations
+        || params.p_cost() != config.parallelism
+}
+
+/// Replaces the stored hash of `password` with one computed with the
+/// configured parameters, unless the password changed in the meantime.
+fn spawn_rehash(
+    pool: Pool<Postgres>,
+    user_id: Uuid,
+    old_password_hash: String,
+    password: Secret<String>,
+    config: PasswordHashConfig,
+) {
+    let span = tracing::info_span!("Rehash password", %user_id);
+    tokio::spawn(
+        async move {
+            let password_hash =
+                match task::spawn_blocking(move || compute_password_hash(&password, &config)).await
+                {
+                    Ok(Ok(password_hash)) => password_hash,
+                    Ok(Err(e)) => return error!("{}", e),
+                    Err(e) => return error!("Error hashing password: {}", e),
+                };
+
+            let result = sqlx::query!(
+                "UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3",
+                password_hash,
+                user_id,
+                old_password_hash,
             )
-            .map_err(|_| AuthError::InvalidCredentials)?;
+            .execute(&pool)
+            .await;
 
-        Ok::<(), AuthError>(())
-    });
+            match result {
+                Ok(_) => info!("Upgraded password hash"),
+                Err(e) => error!("Error storing rehashed password: {}", e),
+            }
+        }
+        .instrument(span),
+    );
+}
 
-    handle
-        .await
-        .map_err(|e| AuthError::UnexpectedError(e.to_string()))??;
+/// The id of the user behind the current session, available as request data
+/// to every handler behind [`reject_anonymous_users`].
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
 
-    Ok(user.id)
+impl Display for UserId {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Middleware for the `/admin` scope: resolves the session's user id into a
+/// [`UserId`] request extension, or redirects to the login page.
+pub async fn reject_anonymous_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    let user_id = session
+        .get_user_id()
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+
+    match user_id {
+        Some(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        None => {
+            let response = HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/login"))
+                .finish();
+            Err(InternalError::from_response("The user has not logged in", response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{compute_password_hash, dummy_password_hash, needs_rehash, verify_password_hash};
+    use crate::config::PasswordHashConfig;
+
+    /// The parameters of `Argon2::default()`.
+    fn default_config() -> PasswordHashConfig {
+        PasswordHashConfig {
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    fn cheap_config() -> PasswordHashConfig {
+        PasswordHashConfig {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_dummy_hash_uses_configured_parameters() {
+        for config in [default_config(), cheap_config()] {
+            assert!(!needs_rehash(&dummy_password_hash(&config), &config));
+        }
+    }
+
+    #[test]
+    fn test_dummy_hash_matches_no_password() {
+        let dummy_hash = dummy_password_hash(&default_config());
+        for password in ["", "password", "admin"] {
+            assert!(verify_password_hash(&dummy_hash, &Secret::new(password.into())).is_err());
+        }
+    }
+
+    #[test]
+    fn test_computed_hash_verifies_and_is_up_to_date() {
+        let config = cheap_config();
+        let password = Secret::new("correct horse".to_string());
+
+        let password_hash = compute_password_hash(&password, &config).unwrap();
+
+        assert!(verify_password_hash(&password_hash, &password).is_ok());
+        assert!(!needs_rehash(&password_hash, &config));
+        assert!(needs_rehash(&password_hash, &default_config()));
+    }
+
+    #[test]
+    fn test_other_algorithms_need_rehash() {
+        let argon2i = "$argon2i$v=19$m=19456,t=2,p=1$qubgfm5sHq6eahgSfRKgpg$V/UNWaPJtNp9v0BLWnZfl2bWJJOEdT1rFHEqm4NM8mg";
+        let version16 = "$argon2id$v=16$m=19456,t=2,p=1$qubgfm5sHq6eahgSfRKgpg$V/UNWaPJtNp9v0BLWnZfl2bWJJOEdT1rFHEqm4NM8mg";
+
+        assert!(needs_rehash(argon2i, &default_config()));
+        assert!(needs_rehash(version16, &default_config()));
+        assert!(needs_rehash("not a hash", &default_config()));
+    }
 }
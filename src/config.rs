@@ -1,137 +1,940 @@
 //! src/config.rs
+//!
+//! Settings are layered, later sources overriding earlier ones:
+//!
+//! 1. `configuration/base.yaml`, shared by every environment;
+//! 2. `configuration/{APP_ENVIRONMENT}.yaml`, where `APP_ENVIRONMENT` is
+//!    `local` (the default) or `production`;
+//! 3. `APP_*` environment variables, with `__` between nested keys, e.g.
+//!    `APP_DATABASE__PASSWORD` or `APP_EMAIL__SMTP__HOST`.
 
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Clone, Debug)]
+use argon2::Params;
+use base64::Engine;
+use lettre::message::Mailbox;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct SmtpConfig {
     pub host: String,
     pub port: u16,
     pub user: String,
-    pub password: String,
+    pub password: Secret<String>,
+    pub default_sender: String,
+    /// Maximum number of pooled SMTP connections.
+    pub pool_size: u32,
+}
+
+/// Settings for a Postmark-style HTTP email API.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpEmailConfig {
+    /// API root the `/email` endpoint is resolved against.
+    pub base_url: String,
+    pub api_token: Secret<String>,
+    pub default_sender: String,
+    /// Upper bound for a single API request, connecting included.
+    #[serde(rename = "timeout_seconds", deserialize_with = "deserialize_seconds")]
+    pub timeout: Duration,
+    /// Upper bound for opening the connection to the API.
+    #[serde(
+        rename = "connect_timeout_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub connect_timeout: Duration,
+}
+
+/// Settings for writing outgoing email to `.eml` files instead of sending it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FileEmailConfig {
+    pub directory: PathBuf,
+    pub default_sender: String,
+}
+
+/// Settings for printing outgoing email to stdout instead of sending it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StdoutEmailConfig {
     pub default_sender: String,
 }
 
-impl SmtpConfig {
-    pub fn new(
-        host: String,
-        port: String,
-        user: String,
-        password: String,
-        default_sender: String,
-    ) -> Self {
-        Self {
-            host,
-            port: port.parse::<u16>().unwrap(),
-            user,
-            password,
-            default_sender,
+/// The provider outgoing email is handed to, picked with `email.provider`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "EmailSettings")]
+pub enum EmailConfig {
+    Smtp(SmtpConfig),
+    Http(HttpEmailConfig),
+    /// Local development: one `.eml` file per email.
+    File(FileEmailConfig),
+    /// Local development: emails are printed to stdout.
+    Stdout(StdoutEmailConfig),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EmailProvider {
+    Smtp,
+    Http,
+    File,
+    Stdout,
+}
+
+/// The `email` section as written in the files: one subsection per provider,
+/// of which only the selected one is kept.
+#[derive(Deserialize)]
+struct EmailSettings {
+    provider: EmailProvider,
+    smtp: SmtpConfig,
+    http: HttpEmailConfig,
+    file: FileEmailConfig,
+    stdout: StdoutEmailConfig,
+}
+
+impl From<EmailSettings> for EmailConfig {
+    fn from(settings: EmailSettings) -> Self {
+        match settings.provider {
+            EmailProvider::Smtp => EmailConfig::Smtp(settings.smtp),
+            EmailProvider::Http => EmailConfig::Http(settings.http),
+            EmailProvider::File => EmailConfig::File(settings.file),
+            EmailProvider::Stdout => EmailConfig::Stdout(settings.stdout),
         }
     }
+}
 
-    pub fn parse_from_env() -> Self {
-        dotenv::dotenv().ok();
+/// Settings for the background workers that deliver newsletter issues.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeliveryConfig {
+    /// Number of workers draining the delivery queue.
+    pub workers: usize,
+    /// How many times a transient failure is retried before the delivery
+    /// is moved to the dead-letter table.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on every subsequent attempt.
+    #[serde(
+        rename = "retry_base_delay_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub retry_base_delay: Duration,
+}
+
+/// Settings for the confirmation emails sent to new subscribers.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConfirmationConfig {
+    /// How long a confirmation link stays valid.
+    #[serde(rename = "token_ttl_seconds", deserialize_with = "deserialize_seconds")]
+    pub token_ttl: Duration,
+    /// Minimum time between two confirmation emails to the same address.
+    #[serde(
+        rename = "resend_interval_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub resend_interval: Duration,
+}
+
+/// Settings for the signed admin session cookie.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SessionConfig {
+    /// Key used to sign session cookies and unsubscribe links, at least 64
+    /// bytes long. Left empty in `local` only, a random key is generated and
+    /// neither sessions nor links sent earlier survive a restart.
+    pub hmac_secret: Secret<String>,
+    /// Only send the cookie over HTTPS. Disable for plain HTTP development.
+    pub secure_cookie: bool,
+}
+
+/// Argon2id parameters passwords are hashed with. Stored hashes computed
+/// with other parameters are upgraded on the user's next successful login.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PasswordHashConfig {
+    /// Memory cost in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl PasswordHashConfig {
+    pub fn params(&self) -> Result<Params, String> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| format!("Invalid Argon2 parameters: {}", e))
+    }
+}
+
+/// Rules new passwords must follow, whether changed or reset.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PasswordPolicyConfig {
+    /// Minimum number of characters.
+    pub min_length: usize,
+    /// Maximum number of characters.
+    pub max_length: usize,
+    /// How many of lowercase letters, uppercase letters, digits and other
+    /// characters must appear, from 1 to 4.
+    pub min_character_classes: usize,
+}
 
-        let host = env::var("EMAIL_HOST").unwrap();
-        let port = env::var("EMAIL_PORT").unwrap();
-        let user = env::var("EMAIL_USER").unwrap();
-        let password = env::var("EMAIL_PASSWORD").unwrap();
-        let default_sender = env::var("EMAIL_DEFAULT_SENDER").unwrap();
+/// Settings for the "forgot password" emails.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PasswordResetConfig {
+    /// How long a reset link stays valid.
+    #[serde(rename = "token_ttl_seconds", deserialize_with = "deserialize_seconds")]
+    pub token_ttl: Duration,
+    /// Minimum time between two reset emails to the same user.
+    #[serde(
+        rename = "resend_interval_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub resend_interval: Duration,
+}
+
+/// Settings for the second factor admin users can turn on.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TwoFactorConfig {
+    /// Base64 encoded 256 bit key the authenticator app secrets are
+    /// encrypted with in the database. Secrets stored under another key can
+    /// no longer be read, so their users have to set up their app again.
+    pub encryption_key: Secret<String>,
+}
 
-        Self {
-            host,
-            port: port.parse::<u16>().unwrap(),
-            user,
-            password,
-            default_sender,
+impl TwoFactorConfig {
+    pub fn encryption_key(&self) -> Result<Vec<u8>, String> {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(self.encryption_key.expose_secret())
+            .map_err(|e| format!("Invalid base64: {}", e))?;
+        if key.len() != 32 {
+            return Err(format!("Expected 32 bytes, got {}", key.len()));
         }
+        Ok(key)
     }
 }
 
-// #[derive(serde::Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct DatabaseConfig {
-    pub url: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: Secret<String>,
+    pub database_name: String,
+    /// `disable`, `allow`, `prefer`, `require`, `verify-ca` or `verify-full`.
+    #[serde(deserialize_with = "deserialize_ssl_mode")]
+    pub ssl_mode: PgSslMode,
+    /// CA certificate the server certificate is verified against, for the
+    /// `verify-*` modes.
+    pub ssl_root_cert: Option<PathBuf>,
+    pub max_connections: u32,
+    /// Connections kept open even when idle.
+    pub min_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    #[serde(
+        rename = "acquire_timeout_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub acquire_timeout: Duration,
+    /// How long a connection above `min_connections` may stay idle before it
+    /// is closed; zero keeps idle connections open.
+    #[serde(
+        rename = "idle_timeout_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub idle_timeout: Duration,
+    /// Server-side limit for a single statement; zero disables it.
+    #[serde(
+        rename = "statement_timeout_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub statement_timeout: Duration,
+    /// Start without waiting for the database, opening connections on first
+    /// use, e.g. while Postgres is still booting next to the application.
+    pub connect_lazily: bool,
+    /// Apply pending migrations when the server starts. Turn off when
+    /// several replicas start together or the application role lacks DDL
+    /// privileges, and run `zero2prod migrate run` instead.
+    pub migrate_on_startup: bool,
 }
 
+impl DatabaseConfig {
+    /// Options for connecting to `database_name`.
+    pub fn connect_options(&self) -> PgConnectOptions {
+        let mut options = PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(&self.username)
+            .password(self.password.expose_secret())
+            .database(&self.database_name)
+            .ssl_mode(self.ssl_mode)
+            .options([(
+                "statement_timeout",
+                self.statement_timeout.as_millis().to_string(),
+            )]);
+        if let Some(ssl_root_cert) = &self.ssl_root_cert {
+            options = options.ssl_root_cert(ssl_root_cert);
+        }
+        options
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout((!self.idle_timeout.is_zero()).then_some(self.idle_timeout))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     pub port: u16,
+    /// Public URL the application is reachable at, used to build links in
+    /// outgoing emails.
+    pub base_url: String,
+    /// How long in-flight requests and email sends get to finish on
+    /// shutdown.
+    #[serde(
+        rename = "shutdown_timeout_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub shutdown_timeout: Duration,
+    #[serde(rename = "database")]
     pub db_config: DatabaseConfig,
-    pub smtp_config: SmtpConfig,
+    #[serde(rename = "email")]
+    pub email_config: EmailConfig,
+    #[serde(rename = "delivery")]
+    pub delivery_config: DeliveryConfig,
+    #[serde(rename = "confirmation")]
+    pub confirmation_config: ConfirmationConfig,
+    #[serde(rename = "session")]
+    pub session_config: SessionConfig,
+    #[serde(rename = "password_hashing")]
+    pub password_hash_config: PasswordHashConfig,
+    #[serde(rename = "password_policy")]
+    pub password_policy_config: PasswordPolicyConfig,
+    #[serde(rename = "password_reset")]
+    pub password_reset_config: PasswordResetConfig,
+    #[serde(rename = "two_factor")]
+    pub two_factor_config: TwoFactorConfig,
+}
+
+/// The deployment the configuration is loaded for, naming the file layered
+/// over `base.yaml`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Environment {
+    Local,
+    Production,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Local => "local",
+            Environment::Production => "production",
+        }
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = String;
+
+    fn try_from(environment: String) -> Result<Self, Self::Error> {
+        match environment.to_lowercase().as_str() {
+            "local" => Ok(Environment::Local),
+            "production" => Ok(Environment::Production),
+            other => Err(format!(
+                "{} is not a supported environment, use either local or production",
+                other
+            )),
+        }
+    }
+}
+
+/// The sections of the configuration a command uses. Only those are
+/// validated, so e.g. migrations can run without email credentials.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Every section, for serving the application.
+    Server,
+    /// The `database` section, for `migrate`.
+    Database,
+    /// The `database`, `password_hashing` and `password_policy` sections,
+    /// for `user`.
+    Users,
 }
 
 impl Config {
-    pub fn new() -> Config {
+    /// Loads the configuration for `APP_ENVIRONMENT` from the `configuration`
+    /// directory and the process environment, `.env` included.
+    ///
+    /// Settings that cannot be parsed are reported together in the returned
+    /// error, one per section. Once everything parses, all problems found in
+    /// the sections `scope` covers are reported together.
+    pub fn load(scope: Scope) -> Result<Config, String> {
         dotenv::dotenv().ok();
 
-        let psql_user = env::var("PSQL_USER").unwrap_or("admin".into());
-        let psql_password = env::var("PSQL_PASSWORD").unwrap_or("admin".into());
-        let psql_database = env::var("PSQL_DATABASE").unwrap_or("newsletter".into());
-        let psql_host = env::var("PSQL_HOST").unwrap_or("localhost".into());
-        let psql_port = env::var("PSQL_PORT").unwrap_or("5432".into());
+        let environment: Environment = env::var("APP_ENVIRONMENT")
+            .unwrap_or("local".into())
+            .try_into()?;
+        let directory = env::current_dir()
+            .map_err(|e| format!("Error reading current directory: {}", e))?
+            .join("configuration");
+
+        Self::load_from(&directory, environment, scope, None)
+    }
+
+    /// Like [`Config::load`], reading `env_vars` instead of the process
+    /// environment when given.
+    pub fn load_from(
+        directory: &Path,
+        environment: Environment,
+        scope: Scope,
+        env_vars: Option<HashMap<String, String>>,
+    ) -> Result<Config, String> {
+        let settings = config::Config::builder()
+            .add_source(config::File::from(directory.join("base.yaml")))
+            .add_source(config::File::from(
+                directory.join(format!("{}.yaml", environment.as_str())),
+            ))
+            .add_source(
+                config::Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .source(env_vars),
+            )
+            .build()
+            .map_err(|e| format!("Invalid configuration: {}", e))?;
+        let mut config: Config = match settings.clone().try_deserialize() {
+            Ok(config) => config,
+            Err(e) => {
+                // Deserialization stops at the first error, so look for one
+                // in every section to report them all at once.
+                let mut problems = parse_problems(&settings);
+                if problems.is_empty() {
+                    problems.push(e.to_string());
+                }
+                return Err(format!("Invalid configuration: {}", problems.join("; ")));
+            }
+        };
 
-        let url = format!(
-            "postgres://{}:{}@{}:{}/{}",
-            psql_user, psql_password, psql_host, psql_port, psql_database
+        // Unsubscribe links already emailed must keep working across restarts
+        // and replicas, so only local development may go without a key.
+        if environment == Environment::Local
+            && config.session_config.hmac_secret.expose_secret().is_empty()
+        {
+            tracing::warn!(
+                "session.hmac_secret not set, sessions and unsubscribe links will not survive a restart"
+            );
+            config.session_config.hmac_secret = Secret::new(
+                rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(64)
+                    .map(char::from)
+                    .collect(),
+            );
+        }
+
+        config.validate(scope)?;
+        Ok(config)
+    }
+
+    fn validate(&self, scope: Scope) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        let db_config = &self.db_config;
+        check_present(&mut problems, "database.host", &db_config.host);
+        check_present(&mut problems, "database.username", &db_config.username);
+        check_present(
+            &mut problems,
+            "database.database_name",
+            &db_config.database_name,
         );
+        if db_config.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".into());
+        }
+        if db_config.min_connections > db_config.max_connections {
+            problems.push("database.min_connections must not exceed max_connections".into());
+        }
+        if db_config.acquire_timeout.is_zero() {
+            problems.push("database.acquire_timeout_seconds must be at least 1".into());
+        }
+
+        if scope != Scope::Database {
+            if let Err(e) = self.password_hash_config.params() {
+                problems.push(format!("password_hashing: {}", e));
+            }
+
+            let policy = &self.password_policy_config;
+            if policy.min_length == 0 {
+                problems.push("password_policy.min_length must be at least 1".into());
+            }
+            if policy.max_length < policy.min_length {
+                problems.push(
+                    "password_policy.max_length must not be lower than password_policy.min_length"
+                        .into(),
+                );
+            }
+            if !(1..=4).contains(&policy.min_character_classes) {
+                problems.push("password_policy.min_character_classes must be from 1 to 4".into());
+            }
+        }
+
+        if scope == Scope::Server {
+            self.validate_server(&mut problems);
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration: {}", problems.join("; ")))
+        }
+    }
+
+    /// Checks the sections only the server uses.
+    fn validate_server(&self, problems: &mut Vec<String>) {
+        check_url(problems, "base_url", &self.base_url);
+
+        match &self.email_config {
+            EmailConfig::Smtp(smtp_config) => {
+                check_present(problems, "email.smtp.host", &smtp_config.host);
+                check_present(problems, "email.smtp.user", &smtp_config.user);
+                check_present(
+                    problems,
+                    "email.smtp.password",
+                    smtp_config.password.expose_secret(),
+                );
+                check_mailbox(
+                    problems,
+                    "email.smtp.default_sender",
+                    &smtp_config.default_sender,
+                );
+                if smtp_config.pool_size == 0 {
+                    problems.push("email.smtp.pool_size must be at least 1".into());
+                }
+            }
+            EmailConfig::Http(http_config) => {
+                check_url(problems, "email.http.base_url", &http_config.base_url);
+                check_present(
+                    problems,
+                    "email.http.api_token",
+                    http_config.api_token.expose_secret(),
+                );
+                check_mailbox(
+                    problems,
+                    "email.http.default_sender",
+                    &http_config.default_sender,
+                );
+                if http_config.timeout.is_zero() {
+                    problems.push("email.http.timeout_seconds must be at least 1".into());
+                }
+                if http_config.connect_timeout.is_zero() {
+                    problems.push("email.http.connect_timeout_seconds must be at least 1".into());
+                }
+            }
+            EmailConfig::File(file_config) => {
+                if file_config.directory.as_os_str().is_empty() {
+                    problems.push("email.file.directory must be set".into());
+                }
+                check_mailbox(
+                    problems,
+                    "email.file.default_sender",
+                    &file_config.default_sender,
+                );
+            }
+            EmailConfig::Stdout(stdout_config) => {
+                check_mailbox(
+                    problems,
+                    "email.stdout.default_sender",
+                    &stdout_config.default_sender,
+                );
+            }
+        }
+
+        if self.delivery_config.workers == 0 {
+            problems.push("delivery.workers must be at least 1".into());
+        }
+
+        if self.session_config.hmac_secret.expose_secret().len() < 64 {
+            problems.push("session.hmac_secret must be at least 64 bytes long".into());
+        }
 
-        let db_config = DatabaseConfig { url };
+        if let Err(e) = self.two_factor_config.encryption_key() {
+            problems.push(format!("two_factor.encryption_key: {}", e));
+        }
 
-        let smtp_config = SmtpConfig::parse_from_env();
+        if self.confirmation_config.token_ttl.is_zero() {
+            problems.push("confirmation.token_ttl_seconds must be at least 1".into());
+        }
+
+        if self.password_reset_config.token_ttl.is_zero() {
+            problems.push("password_reset.token_ttl_seconds must be at least 1".into());
+        }
+    }
+}
 
-        Config {
-            port: 3000,
-            db_config,
-            smtp_config,
+/// Parses every top-level setting of [`Config`] on its own and returns why
+/// the ones that fail do.
+fn parse_problems(settings: &config::Config) -> Vec<String> {
+    fn check<T: DeserializeOwned>(
+        settings: &config::Config,
+        key: &str,
+        problems: &mut Vec<String>,
+    ) {
+        // Going through a map keeps the path of the failing field in the
+        // error, which `config::Config::get` reduces to `key`.
+        let parsed = settings.get::<config::Value>(key).and_then(|value| {
+            config::Config::builder()
+                .set_override(key, value)?
+                .build()?
+                .try_deserialize::<HashMap<String, T>>()
+        });
+        if let Err(e) = parsed {
+            problems.push(e.to_string());
         }
     }
+
+    let mut problems = Vec::new();
+    check::<u16>(settings, "port", &mut problems);
+    check::<String>(settings, "base_url", &mut problems);
+    check::<u64>(settings, "shutdown_timeout_seconds", &mut problems);
+    check::<DatabaseConfig>(settings, "database", &mut problems);
+    check::<EmailConfig>(settings, "email", &mut problems);
+    check::<DeliveryConfig>(settings, "delivery", &mut problems);
+    check::<ConfirmationConfig>(settings, "confirmation", &mut problems);
+    check::<SessionConfig>(settings, "session", &mut problems);
+    check::<PasswordHashConfig>(settings, "password_hashing", &mut problems);
+    check::<PasswordPolicyConfig>(settings, "password_policy", &mut problems);
+    check::<PasswordResetConfig>(settings, "password_reset", &mut problems);
+    problems
+}
+
+fn check_present(problems: &mut Vec<String>, key: &str, value: &str) {
+    if value.trim().is_empty() {
+        problems.push(format!("{} must be set", key));
+    }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self::new()
+fn check_url(problems: &mut Vec<String>, key: &str, value: &str) {
+    if !(value.starts_with("http://") || value.starts_with("https://")) {
+        problems.push(format!("{} must be an http(s) URL, got {:?}", key, value));
     }
 }
 
+fn check_mailbox(problems: &mut Vec<String>, key: &str, value: &str) {
+    if value.trim().is_empty() {
+        problems.push(format!("{} must be set", key));
+    } else if let Err(e) = value.parse::<Mailbox>() {
+        problems.push(format!(
+            "{} must be an email address ({}), got {:?}",
+            key, e, value
+        ));
+    }
+}
+
+fn deserialize_ssl_mode<'de, D>(deserializer: D) -> Result<PgSslMode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ssl_mode = String::deserialize(deserializer)?;
+    ssl_mode.parse().map_err(serde::de::Error::custom)
+}
+
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
 #[cfg(test)]
 mod tests {
-    use fake::faker::internet::en::{DomainSuffix, Password, SafeEmail, Username};
-    use fake::faker::number::en::NumberWithFormat;
-    use fake::Fake;
-    use std::env::{remove_var, set_var};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use secrecy::ExposeSecret;
+    use sqlx::postgres::PgSslMode;
+
+    use crate::config::{Config, EmailConfig, Environment, Scope};
 
-    use crate::config::SmtpConfig;
+    fn configuration_directory() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("configuration")
+    }
+
+    fn env_vars(vars: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+        Some(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_local_configuration_is_valid() {
+        let config = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[]),
+        )
+        .unwrap();
+
+        assert_eq!(3000, config.port);
+        assert!(matches!(config.email_config, EmailConfig::Stdout(_)));
+        assert!(!config.session_config.secure_cookie);
+        assert_eq!(64, config.session_config.hmac_secret.expose_secret().len());
+    }
+
+    #[test]
+    fn test_env_vars_override_files() {
+        let config = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[
+                ("APP_PORT", "8080"),
+                ("APP_DATABASE__HOST", "db"),
+                ("APP_DELIVERY__RETRY_BASE_DELAY_SECONDS", "5"),
+                ("APP_EMAIL__PROVIDER", "smtp"),
+                ("APP_EMAIL__SMTP__HOST", "smtp.example.com"),
+                ("APP_EMAIL__SMTP__PORT", "2525"),
+                ("APP_EMAIL__SMTP__USER", "user"),
+                ("APP_EMAIL__SMTP__PASSWORD", "12345"),
+                ("APP_EMAIL__SMTP__DEFAULT_SENDER", "newsletter@example.com"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(8080, config.port);
+        assert_eq!("db", config.db_config.host);
+        assert_eq!(
+            Duration::from_secs(5),
+            config.delivery_config.retry_base_delay
+        );
+        let EmailConfig::Smtp(smtp_config) = config.email_config else {
+            panic!("Expected the smtp provider");
+        };
+        assert_eq!("smtp.example.com", smtp_config.host);
+        assert_eq!(2525, smtp_config.port);
+        assert_eq!("12345", smtp_config.password.expose_secret());
+    }
+
+    #[test]
+    fn test_production_reports_every_missing_setting() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Production,
+            Scope::Server,
+            env_vars(&[("APP_SESSION__HMAC_SECRET", "too short")]),
+        )
+        .unwrap_err();
+
+        assert!(error.starts_with("Invalid configuration:"));
+        for key in [
+            "email.smtp.host",
+            "email.smtp.user",
+            "email.smtp.password",
+            "email.smtp.default_sender",
+            "session.hmac_secret",
+            "two_factor.encryption_key",
+        ] {
+            assert!(error.contains(key), "{} missing from {}", key, error);
+        }
+    }
 
     #[test]
-    fn smtp_config_from_env() {
-        let hostname = generate_hostname();
-        let username: String = Username().fake();
-        let port: String = NumberWithFormat("###").fake();
-        let password: String = Password(8..16).fake();
-        let default_sender: String = SafeEmail().fake();
-
-        set_var("EMAIL_HOST", hostname.clone());
-        set_var("EMAIL_USER", username.clone());
-        set_var("EMAIL_PORT", port.clone());
-        set_var("EMAIL_PASSWORD", password.clone());
-        set_var("EMAIL_DEFAULT_SENDER", default_sender.clone());
-
-        let smtp_config = SmtpConfig::parse_from_env();
-
-        remove_var("EMAIL_HOST");
-        remove_var("EMAIL_USER");
-        remove_var("EMAIL_PORT");
-        remove_var("EMAIL_PASSWORD");
-        remove_var("EMAIL_DEFAULT_SENDER");
-
-        assert_eq!(hostname, smtp_config.host);
-        assert_eq!(username, smtp_config.user);
-        assert_eq!(port.parse::<u16>().unwrap(), smtp_config.port);
-        assert_eq!(password, smtp_config.password);
-        assert_eq!(default_sender, smtp_config.default_sender);
-    }
-
-    fn generate_hostname() -> String {
-        let domain: String = Username().fake();
-        let domain_suffix: String = DomainSuffix().fake();
-        format!("smtp.{}.{}", domain, domain_suffix)
+    fn test_production_requires_a_session_secret() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Production,
+            Scope::Server,
+            env_vars(&[
+                ("APP_EMAIL__SMTP__HOST", "smtp.example.com"),
+                ("APP_EMAIL__SMTP__USER", "user"),
+                ("APP_EMAIL__SMTP__PASSWORD", "12345"),
+                ("APP_EMAIL__SMTP__DEFAULT_SENDER", "newsletter@example.com"),
+            ]),
+        )
+        .unwrap_err();
+
+        assert!(error.contains("session.hmac_secret"), "{}", error);
+    }
+
+    #[test]
+    fn test_commands_only_validate_the_sections_they_use() {
+        let config = Config::load_from(
+            &configuration_directory(),
+            Environment::Production,
+            Scope::Database,
+            env_vars(&[("APP_PASSWORD_HASHING__ITERATIONS", "0")]),
+        );
+        assert!(config.is_ok(), "{:?}", config.err());
+
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Production,
+            Scope::Users,
+            env_vars(&[("APP_PASSWORD_HASHING__ITERATIONS", "0")]),
+        )
+        .unwrap_err();
+        assert!(error.contains("password_hashing"), "{}", error);
+        assert!(!error.contains("email"), "{}", error);
+        assert!(!error.contains("session"), "{}", error);
+
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Production,
+            Scope::Database,
+            env_vars(&[("APP_DATABASE__MAX_CONNECTIONS", "0")]),
+        )
+        .unwrap_err();
+        assert!(error.contains("database.max_connections"), "{}", error);
+    }
+
+    #[test]
+    fn test_database_pool_settings() {
+        let config = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[
+                ("APP_DATABASE__SSL_MODE", "verify-full"),
+                ("APP_DATABASE__SSL_ROOT_CERT", "/etc/ssl/db-ca.pem"),
+                ("APP_DATABASE__IDLE_TIMEOUT_SECONDS", "0"),
+            ]),
+        )
+        .unwrap();
+
+        let db_config = config.db_config;
+        assert!(matches!(db_config.ssl_mode, PgSslMode::VerifyFull));
+        assert_eq!(
+            Some(PathBuf::from("/etc/ssl/db-ca.pem")),
+            db_config.ssl_root_cert
+        );
+        assert_eq!(None, db_config.pool_options().get_idle_timeout());
+
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[
+                ("APP_DATABASE__MIN_CONNECTIONS", "20"),
+                ("APP_DATABASE__MAX_CONNECTIONS", "5"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(error.contains("database.min_connections"), "{}", error);
+
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[("APP_DATABASE__SSL_MODE", "sometimes")]),
+        )
+        .unwrap_err();
+        assert!(error.contains("sometimes"), "{}", error);
+    }
+
+    #[test]
+    fn test_unparsable_settings_are_reported_together() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[
+                ("APP_PORT", "http"),
+                ("APP_DATABASE__PORT", "abc"),
+                ("APP_DELIVERY__WORKERS", "many"),
+            ]),
+        )
+        .unwrap_err();
+
+        assert!(error.starts_with("Invalid configuration:"));
+        for key in ["port", "database.port", "delivery.workers"] {
+            assert!(
+                error.contains(&format!("`{}`", key)),
+                "{} missing from {}",
+                key,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_email_provider_is_rejected() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[("APP_EMAIL__PROVIDER", "carrier-pigeon")]),
+        )
+        .unwrap_err();
+
+        assert!(error.contains("carrier-pigeon"), "{}", error);
+    }
+
+    #[test]
+    fn test_invalid_password_hashing_parameters_are_rejected() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[("APP_PASSWORD_HASHING__ITERATIONS", "0")]),
+        )
+        .unwrap_err();
+
+        assert!(error.contains("password_hashing"), "{}", error);
+    }
+
+    #[test]
+    fn test_zero_token_ttls_are_rejected() {
+        let error = Config::load_from(
+            &configuration_directory(),
+            Environment::Local,
+            Scope::Server,
+            env_vars(&[
+                ("APP_CONFIRMATION__TOKEN_TTL_SECONDS", "0"),
+                ("APP_PASSWORD_RESET__TOKEN_TTL_SECONDS", "0"),
+            ]),
+        )
+        .unwrap_err();
+
+        assert!(
+            error.contains("confirmation.token_ttl_seconds"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("password_reset.token_ttl_seconds"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_two_factor_encryption_key_must_be_256_bits() {
+        for key in ["not base64!", "c2hvcnQ="] {
+            let error = Config::load_from(
+                &configuration_directory(),
+                Environment::Local,
+                Scope::Server,
+                env_vars(&[("APP_TWO_FACTOR__ENCRYPTION_KEY", key)]),
+            )
+            .unwrap_err();
+
+            assert!(error.contains("two_factor.encryption_key"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_unknown_environment_is_rejected() {
+        assert!(Environment::try_from("staging".to_string()).is_err());
+        assert_eq!(
+            Ok(Environment::Production),
+            Environment::try_from("Production".to_string())
+        );
     }
 }
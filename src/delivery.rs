@@ -0,0 +1,369 @@
+//! src/delivery.rs
+
+use std::{sync::Arc, time::Duration};
+
+use askama::Template;
+use chrono::Utc;
+use rand::Rng;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, instrument, warn, Instrument};
+use uuid::Uuid;
+
+use crate::config::DeliveryConfig;
+use crate::domain::subscriber::SubscriptionStatus;
+use crate::email::{Email, EmailError, EmailService};
+use crate::templates::{NewsletterFooterHtmlTemplate, NewsletterFooterTxtTemplate};
+use crate::unsubscribe::UnsubscribeLinks;
+
+/// How long an idle worker waits before polling the queue again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between two attempts of the same delivery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The result of a single attempt to pull a task off the delivery queue.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct NewsletterIssue {
+    subject: String,
+    text_content: String,
+    html_content: String,
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i32,
+    /// `None` when the subscription no longer exists.
+    subscriber_id: Option<Uuid>,
+    subscriber_status: Option<SubscriptionStatus>,
+}
+
+/// Spawns the configured number of workers that drain `issue_delivery_queue`
+/// until `shutdown` is cancelled.
+///
+/// A worker always settles the delivery it is working on before it stops.
+pub fn spawn_workers(
+    config: &DeliveryConfig,
+    pool: Pool<Postgres>,
+    email_service: Arc<dyn EmailService + Send + Sync>,
+    unsubscribe_links: UnsubscribeLinks,
+    shutdown: CancellationToken,
+) -> Vec<JoinHandle<()>> {
+    (0..config.workers)
+        .map(|_| {
+            tokio::spawn(run_worker_until_stopped(
+                config.clone(),
+                pool.clone(),
+                email_service.clone(),
+                unsubscribe_links.clone(),
+                shutdown.clone(),
+            ))
+        })
+        .collect()
+}
+
+async fn run_worker_until_stopped(
+    config: DeliveryConfig,
+    pool: Pool<Postgres>,
+    email_service: Arc<dyn EmailService + Send + Sync>,
+    unsubscribe_links: UnsubscribeLinks,
+    shutdown: CancellationToken,
+) {
+    while !shutdown.is_cancelled() {
+        match try_execute_task(&pool, email_service.as_ref(), &config, &unsubscribe_links).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                sleep_unless_cancelled(POLL_INTERVAL, &shutdown).await
+            }
+            Err(e) => {
+                error!("Error executing delivery task: {}", e);
+                sleep_unless_cancelled(POLL_INTERVAL, &shutdown).await;
+            }
+        }
+    }
+}
+
+/// Sleeps for `duration`, waking up early when `shutdown` is cancelled.
+pub(crate) async fn sleep_unless_cancelled(duration: Duration, shutdown: &CancellationToken) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = shutdown.cancelled() => {}
+    }
+}
+
+/// Claims one due delivery, sends it and settles it.
+///
+/// The row is locked with `FOR UPDATE SKIP LOCKED` for the lifetime of the
+/// transaction, so concurrent workers never pick up the same delivery. A
+/// successful send removes the row; a transient failure reschedules it with
+/// jittered exponential backoff until `max_retries` is exhausted, after which
+/// it joins permanent failures in `issue_delivery_dead_letters`.
+///
+/// Every email carries a signed one-click unsubscribe link, both in its body
+/// and in the RFC 8058 `List-Unsubscribe` headers. Subscribers who are no
+/// longer confirmed by the time their delivery comes up are skipped.
+#[instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = tracing::field::Empty,
+        subscriber_email = tracing::field::Empty
+    )
+)]
+pub async fn try_execute_task(
+    pool: &Pool<Postgres>,
+    email_service: &(dyn EmailService + Send + Sync),
+    config: &DeliveryConfig,
+    unsubscribe_links: &UnsubscribeLinks,
+) -> Result<ExecutionOutcome, String> {
+    let Some((transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(task.newsletter_issue_id),
+        )
+        .record(
+            "subscriber_email",
+            tracing::field::display(&task.subscriber_email),
+        );
+
+    let subscriber_id = match (task.subscriber_id, task.subscriber_status) {
+        (Some(subscriber_id), Some(SubscriptionStatus::Confirmed)) => subscriber_id,
+        _ => {
+            info!("Subscriber is no longer confirmed, skipping delivery");
+            delete_task(transaction, &task).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    let issue = get_issue(pool, task.newsletter_issue_id).await?;
+
+    let unsubscribe_link = unsubscribe_links.link(subscriber_id);
+    let html = render_with_footer(
+        &issue.html_content,
+        NewsletterFooterHtmlTemplate {
+            link: &unsubscribe_link,
+        },
+    )?;
+    let plaintext = render_with_footer(
+        &issue.text_content,
+        NewsletterFooterTxtTemplate {
+            link: &unsubscribe_link,
+        },
+    )?;
+    let list_unsubscribe = format!("<{}>", unsubscribe_link);
+
+    let email = Email {
+        to: &task.subscriber_email,
+        html: &html,
+        from: "",
+        subject: &issue.subject,
+        reply_to: "",
+        plaintext: &plaintext,
+        headers: &[
+            ("List-Unsubscribe", &list_unsubscribe),
+            ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ],
+    };
+
+    match email_service.send(email).await {
+        Ok(()) => {
+            info!("Delivered newsletter issue");
+            delete_task(transaction, &task).await?;
+        }
+        Err(e) if e.is_transient() && (task.n_retries as u32) < config.max_retries => {
+            let delay = retry_delay(config.retry_base_delay, task.n_retries as u32);
+            warn!(
+                "Failed to deliver newsletter issue, retrying in {:?}: {}",
+                delay, e
+            );
+            schedule_retry(transaction, &task, delay).await?;
+        }
+        Err(e) => {
+            error!("Failed to deliver newsletter issue, dead-lettering: {}", e);
+            dead_letter(transaction, &task, &e).await?;
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+fn render_with_footer(content: &str, footer: impl Template) -> Result<String, String> {
+    let footer = footer
+        .render()
+        .map_err(|e| format!("Error rendering newsletter footer: {}", e))?;
+    Ok(format!("{}\n{}", content, footer))
+}
+
+/// Jittered exponential backoff: a random delay between half and all of
+/// `base_delay * 2^n_retries`, capped at [`MAX_RETRY_DELAY`].
+pub(crate) fn retry_delay(base_delay: Duration, n_retries: u32) -> Duration {
+    let delay = base_delay
+        .saturating_mul(2u32.saturating_pow(n_retries))
+        .min(MAX_RETRY_DELAY);
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+async fn dequeue_task(
+    pool: &Pool<Postgres>,
+) -> Result<Option<(Transaction<'static, Postgres>, DeliveryTask)>, String> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            s.id as "subscriber_id?",
+            s.status as "subscriber_status?: SubscriptionStatus"
+        FROM issue_delivery_queue q
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .instrument(tracing::info_span!("dequeue delivery task query"))
+    .await
+    .map_err(|e| format!("Error dequeuing delivery task: {}", e))?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn delete_task(
+    mut transaction: Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("delete delivery task query"))
+    .await
+    .map_err(|e| format!("Error deleting delivery task: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing delivery task: {}", e))
+}
+
+async fn schedule_retry(
+    mut transaction: Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), String> {
+    let execute_after = Utc::now()
+        + chrono::Duration::from_std(delay).map_err(|e| format!("Invalid delay: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("schedule delivery retry query"))
+    .await
+    .map_err(|e| format!("Error scheduling delivery retry: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing delivery retry: {}", e))
+}
+
+async fn dead_letter(
+    mut transaction: Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+    error: &EmailError,
+) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters
+            (newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries + 1,
+        error.to_string(),
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("dead-letter delivery task query"))
+    .await
+    .map_err(|e| format!("Error dead-lettering delivery task: {}", e))?;
+
+    delete_task(transaction, task).await
+}
+
+async fn get_issue(pool: &Pool<Postgres>, issue_id: Uuid) -> Result<NewsletterIssue, String> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT subject, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .instrument(tracing::info_span!("get newsletter issue query"))
+    .await
+    .map_err(|e| format!("Error fetching newsletter issue {}: {}", issue_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::delivery::{retry_delay, MAX_RETRY_DELAY};
+
+    #[test]
+    fn test_retry_delay_grows_exponentially() {
+        let base_delay = Duration::from_secs(10);
+        for n_retries in 0..5 {
+            let ceiling = base_delay * 2u32.pow(n_retries);
+            let delay = retry_delay(base_delay, n_retries);
+            assert!(delay >= ceiling / 2 && delay <= ceiling);
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        let delay = retry_delay(Duration::from_secs(10), 64);
+        assert!(delay <= MAX_RETRY_DELAY);
+        assert!(delay >= MAX_RETRY_DELAY / 2);
+    }
+}
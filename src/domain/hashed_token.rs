@@ -0,0 +1,93 @@
+//! src/domain/hashed_token.rs
+
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha3::{Digest, Sha3_256};
+use subtle::ConstantTimeEq;
+
+/// A single-use token as emailed to a user, e.g. to confirm a subscription
+/// or reset a password.
+///
+/// Only [`HashedToken::hash`] is ever stored, so reading the table the token
+/// is kept in is not enough to use it.
+#[derive(Debug)]
+pub struct HashedToken(String);
+
+impl HashedToken {
+    /// 62^32, i.e. about 190 bits of entropy.
+    const LENGTH: usize = 32;
+
+    /// Draws a new token from the operating system's CSPRNG.
+    pub fn generate() -> HashedToken {
+        let token = OsRng
+            .sample_iter(&Alphanumeric)
+            .take(Self::LENGTH)
+            .map(char::from)
+            .collect();
+        HashedToken(token)
+    }
+
+    /// Returns `None` if `s` cannot be a generated token; callers report
+    /// that as their own invalid token error.
+    pub fn parse(s: String) -> Option<HashedToken> {
+        if s.len() != Self::LENGTH || !s.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        Some(HashedToken(s))
+    }
+
+    pub fn hash(&self) -> Vec<u8> {
+        Sha3_256::digest(self.0.as_bytes()).to_vec()
+    }
+
+    /// Compares `stored_hash` with the hash of this token in constant time.
+    pub fn matches(&self, stored_hash: &[u8]) -> bool {
+        self.hash().ct_eq(stored_hash).into()
+    }
+}
+
+impl AsRef<str> for HashedToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some};
+
+    use crate::domain::HashedToken;
+
+    #[test]
+    fn test_generated_tokens_parse() {
+        let token = HashedToken::generate();
+        assert_some!(HashedToken::parse(token.as_ref().to_string()));
+    }
+
+    #[test]
+    fn test_generated_tokens_differ() {
+        assert_ne!(
+            HashedToken::generate().as_ref(),
+            HashedToken::generate().as_ref()
+        );
+    }
+
+    #[test]
+    fn test_malformed_tokens_are_rejected() {
+        assert_none!(HashedToken::parse("".into()));
+        assert_none!(HashedToken::parse("a".repeat(31) + "!"));
+        assert_none!(HashedToken::parse(
+            "67e55044-10b1-426f-9247-bb680e5fe0c8".into()
+        ));
+    }
+
+    #[test]
+    fn test_token_matches_its_own_hash_only() {
+        let token = HashedToken::generate();
+        assert!(token.matches(&token.hash()));
+        assert!(!token.matches(&HashedToken::generate().hash()));
+        assert!(!token.matches(&[]));
+    }
+}
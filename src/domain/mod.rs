@@ -1,4 +1,8 @@
 //! src/domain/mod.rs
 
+mod hashed_token;
 pub mod newsletter;
+pub mod password;
 pub mod subscriber;
+
+pub use hashed_token::HashedToken;
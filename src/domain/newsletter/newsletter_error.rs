@@ -7,7 +7,6 @@ use std::fmt::{Display, Error, Formatter};
 pub enum NewsletterError {
     PublishError(String),
     DatabaseError(sqlx::Error),
-    EmailError(String),
     AuthError(),
     HasherError(argon2::Error),
 }
@@ -17,7 +16,6 @@ impl Display for NewsletterError {
         match self {
             NewsletterError::PublishError(e) => write!(f, "Publish Error: {}", e),
             NewsletterError::DatabaseError(e) => write!(f, "Database Error: {}", e),
-            NewsletterError::EmailError(e) => write!(f, "Error sending email: {}", e),
             NewsletterError::AuthError() => write!(f, "Unauthorized"),
             NewsletterError::HasherError(e) => write!(f, "Error hashing password: {}", e),
         }
@@ -33,9 +31,6 @@ impl ResponseError for NewsletterError {
             NewsletterError::DatabaseError(ref error) => {
                 HttpResponse::InternalServerError().json(error.to_string())
             }
-            NewsletterError::EmailError(ref message) => {
-                HttpResponse::InternalServerError().json(message)
-            }
             NewsletterError::AuthError() => HttpResponse::Unauthorized().finish(),
             NewsletterError::HasherError(ref _error) => HttpResponse::Unauthorized().finish(),
         }
@@ -0,0 +1,5 @@
+mod new_password;
+mod password_error;
+
+pub use new_password::NewPassword;
+pub use password_error::PasswordError;
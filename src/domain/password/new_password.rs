@@ -0,0 +1,99 @@
+//! src/domain/password/new_password.rs
+
+use secrecy::{ExposeSecret, Secret};
+
+use crate::config::PasswordPolicyConfig;
+use crate::domain::password::PasswordError;
+
+/// A password that follows the password policy, about to be hashed and
+/// stored.
+#[derive(Debug)]
+pub struct NewPassword(Secret<String>);
+
+impl NewPassword {
+    pub fn parse(
+        password: Secret<String>,
+        policy: &PasswordPolicyConfig,
+    ) -> Result<NewPassword, PasswordError> {
+        let exposed = password.expose_secret();
+        let length = exposed.chars().count();
+
+        if length < policy.min_length {
+            return Err(PasswordError::WeakPassword(format!(
+                "The new password must be at least {} characters long",
+                policy.min_length
+            )));
+        }
+        if length > policy.max_length {
+            return Err(PasswordError::WeakPassword(format!(
+                "The new password must be at most {} characters long",
+                policy.max_length
+            )));
+        }
+
+        let classes: [fn(char) -> bool; 3] = [char::is_lowercase, char::is_uppercase, |c| {
+            c.is_ascii_digit()
+        }];
+        let mut used_classes = classes
+            .iter()
+            .filter(|class| exposed.chars().any(class))
+            .count();
+        if exposed
+            .chars()
+            .any(|c| !classes.iter().any(|class| class(c)))
+        {
+            used_classes += 1;
+        }
+        if used_classes < policy.min_character_classes {
+            return Err(PasswordError::WeakPassword(format!(
+                "The new password must mix at least {} of lowercase letters, uppercase letters, digits and other characters",
+                policy.min_character_classes
+            )));
+        }
+
+        Ok(NewPassword(password))
+    }
+}
+
+impl AsRef<Secret<String>> for NewPassword {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use crate::config::PasswordPolicyConfig;
+    use crate::domain::password::NewPassword;
+
+    fn policy(min_character_classes: usize) -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length: 12,
+            max_length: 16,
+            min_character_classes,
+        }
+    }
+
+    fn parse(password: &str, policy: &PasswordPolicyConfig) -> Result<NewPassword, String> {
+        NewPassword::parse(Secret::new(password.to_string()), policy).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_length_is_counted_in_characters() {
+        assert_err!(parse("elevenchars", &policy(1)));
+        assert_ok!(parse("twelve chars", &policy(1)));
+        assert_ok!(parse("ééééééééééééé", &policy(1)));
+        assert_err!(parse("seventeen chars!!", &policy(1)));
+    }
+
+    #[test]
+    fn test_character_classes_are_counted() {
+        assert_err!(parse("alllowercase", &policy(2)));
+        assert_ok!(parse("lowerUPPERcase", &policy(2)));
+        assert_err!(parse("lowerUPPERcase", &policy(3)));
+        assert_ok!(parse("lower UPPER 42", &policy(4)));
+    }
+}
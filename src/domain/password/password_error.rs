@@ -0,0 +1,48 @@
+//! src/domain/password/password_error.rs
+
+use actix_web::{error::ResponseError, HttpResponse};
+use std::fmt::{Display, Error, Formatter};
+
+use crate::email::EmailError;
+
+#[derive(Debug)]
+pub enum PasswordError {
+    /// The new password breaks the password policy.
+    WeakPassword(String),
+    InvalidToken,
+    DatabaseError(sqlx::Error),
+    EmailError(EmailError),
+    UnexpectedError(String),
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            PasswordError::WeakPassword(e) => write!(f, "{}", e),
+            PasswordError::InvalidToken => {
+                write!(f, "The reset link is invalid or has expired")
+            }
+            PasswordError::DatabaseError(e) => write!(f, "Database Error: {}", e),
+            PasswordError::EmailError(e) => write!(f, "Error sending email: {}", e),
+            PasswordError::UnexpectedError(e) => write!(f, "Unexpected error: {}", e),
+        }
+    }
+}
+
+impl ResponseError for PasswordError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PasswordError::WeakPassword(ref message) => HttpResponse::BadRequest().json(message),
+            PasswordError::InvalidToken => HttpResponse::BadRequest().json(self.to_string()),
+            PasswordError::DatabaseError(ref error) => {
+                HttpResponse::InternalServerError().json(error.to_string())
+            }
+            PasswordError::EmailError(ref error) => {
+                HttpResponse::build(error.status_code()).json(error.to_string())
+            }
+            PasswordError::UnexpectedError(ref message) => {
+                HttpResponse::InternalServerError().json(message)
+            }
+        }
+    }
+}
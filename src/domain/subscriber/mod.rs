@@ -1,14 +1,16 @@
 mod subscriber_email;
 mod subscriber_error;
 mod subscriber_name;
+mod subscription_status;
 
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_error::SubscriberError;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::SubscriptionStatus;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Subscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
-    pub status: String,
+    pub status: SubscriptionStatus,
 }
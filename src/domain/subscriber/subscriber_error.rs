@@ -3,12 +3,17 @@
 use actix_web::{error::ResponseError, HttpResponse};
 use std::fmt::{Display, Error, Formatter};
 
+use crate::domain::subscriber::SubscriptionStatus;
+use crate::email::EmailError;
+
 #[derive(Debug)]
 pub enum SubscriberError {
     ParseError(String),
     DatabaseError(sqlx::Error),
-    EmailError(String),
-    InvalidToken(String),
+    EmailError(EmailError),
+    InvalidToken,
+    InvalidStatusTransition(SubscriptionStatus, SubscriptionStatus),
+    RateLimited(String),
 }
 
 impl Display for SubscriberError {
@@ -17,7 +22,13 @@ impl Display for SubscriberError {
             SubscriberError::ParseError(e) => write!(f, "Parse Error: {}", e),
             SubscriberError::DatabaseError(e) => write!(f, "Database Error: {}", e),
             SubscriberError::EmailError(e) => write!(f, "Error sending email: {}", e),
-            SubscriberError::InvalidToken(e) => write!(f, "Invalid token: {}", e),
+            SubscriberError::InvalidToken => {
+                write!(f, "The link is invalid or has expired")
+            }
+            SubscriberError::InvalidStatusTransition(from, to) => {
+                write!(f, "Cannot change subscription from {} to {}", from, to)
+            }
+            SubscriberError::RateLimited(e) => write!(f, "Too many requests: {}", e),
         }
     }
 }
@@ -29,11 +40,15 @@ impl ResponseError for SubscriberError {
             SubscriberError::DatabaseError(ref error) => {
                 HttpResponse::InternalServerError().json(error.to_string())
             }
-            SubscriberError::EmailError(ref message) => {
-                HttpResponse::InternalServerError().json(message)
+            SubscriberError::EmailError(ref error) => {
+                HttpResponse::build(error.status_code()).json(error.to_string())
+            }
+            SubscriberError::InvalidToken => HttpResponse::BadRequest().json(self.to_string()),
+            SubscriberError::InvalidStatusTransition(..) => {
+                HttpResponse::Conflict().json(self.to_string())
             }
-            SubscriberError::InvalidToken(ref token) => {
-                HttpResponse::BadRequest().json(format!("Invalid token: {}", token))
+            SubscriberError::RateLimited(ref message) => {
+                HttpResponse::TooManyRequests().json(message)
             }
         }
     }
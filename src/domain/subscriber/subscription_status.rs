@@ -0,0 +1,94 @@
+//! src/domain/subscriber/subscription_status.rs
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Lifecycle of a subscription, stored as the `subscription_status` Postgres
+/// enum.
+///
+/// Only `Confirmed` subscribers receive newsletter issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "subscription_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionStatus {
+    /// Signed up, waiting for the confirmation link to be followed.
+    Pending,
+    Confirmed,
+    /// Opted out; signing up again starts over as `Pending`.
+    Unsubscribed,
+    /// The address is undeliverable.
+    Bounced,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Pending => "pending",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Bounced => "bounced",
+        }
+    }
+
+    /// Whether a subscription may move from `self` to `next`.
+    ///
+    /// Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Confirmed)
+                | (Pending | Confirmed | Bounced, Unsubscribed)
+                | (Pending | Confirmed | Unsubscribed, Bounced)
+                | (Unsubscribed | Bounced, Pending)
+        )
+    }
+}
+
+impl Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber::SubscriptionStatus::{self, *};
+
+    const ALL: [SubscriptionStatus; 4] = [Pending, Confirmed, Unsubscribed, Bounced];
+
+    #[test]
+    fn test_allowed_transitions() {
+        assert!(Pending.can_transition_to(Confirmed));
+        assert!(Confirmed.can_transition_to(Unsubscribed));
+        assert!(Unsubscribed.can_transition_to(Pending));
+        assert!(Bounced.can_transition_to(Pending));
+    }
+
+    #[test]
+    fn test_any_status_but_bounced_can_bounce() {
+        for status in ALL.into_iter().filter(|status| *status != Bounced) {
+            assert!(status.can_transition_to(Bounced), "{} -> bounced", status);
+        }
+    }
+
+    #[test]
+    fn test_forbidden_transitions() {
+        assert!(!Confirmed.can_transition_to(Pending));
+        assert!(!Unsubscribed.can_transition_to(Confirmed));
+        assert!(!Bounced.can_transition_to(Confirmed));
+    }
+
+    #[test]
+    fn test_staying_in_place_is_not_a_transition() {
+        for status in ALL {
+            assert!(
+                !status.can_transition_to(status),
+                "{} -> {}",
+                status,
+                status
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! src/email/file.rs
+
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use tracing::info;
+
+use crate::config::FileEmailConfig;
+use crate::email::{build_message, Email, EmailError, EmailService};
+
+/// Writes every email as an `.eml` file into `FileEmailConfig::directory`,
+/// where it can be opened with any mail client.
+#[derive(Debug)]
+pub struct FileEmailService {
+    config: FileEmailConfig,
+    file_transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileEmailService {
+    /// Creates `FileEmailConfig::directory` if it does not exist yet, and
+    /// fails if that is not possible.
+    pub fn new(config: FileEmailConfig) -> Result<Self, String> {
+        std::fs::create_dir_all(&config.directory).map_err(|e| {
+            format!(
+                "Error creating email directory {}: {}",
+                config.directory.display(),
+                e
+            )
+        })?;
+        let file_transport = AsyncFileTransport::<Tokio1Executor>::new(&config.directory);
+
+        Ok(Self {
+            config,
+            file_transport,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailService for FileEmailService {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError> {
+        let message = build_message(&email, &self.config.default_sender)?;
+        let id = self
+            .file_transport
+            .send(message)
+            .await
+            .map_err(|e| EmailError::Transport {
+                code: None,
+                message: e.to_string(),
+            })?;
+
+        info!(
+            "Wrote email to {}",
+            self.config.directory.join(format!("{}.eml", id)).display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::config::FileEmailConfig;
+    use crate::email::{Email, EmailError, EmailService, FileEmailService};
+
+    fn email(to: &str) -> Email<'_> {
+        Email {
+            to,
+            html: "<p>Confirm</p>",
+            from: "",
+            subject: "Confirm your subscription",
+            reply_to: "",
+            plaintext: "Confirm",
+            headers: &[],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_writes_eml_file() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_service = FileEmailService::new(FileEmailConfig {
+            directory: directory.clone(),
+            default_sender: "newsletter@localhost".into(),
+        })
+        .unwrap();
+
+        email_service
+            .send(email("reader@example.com"))
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(1, files.len());
+        assert_eq!("eml", files[0].extension().unwrap());
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("To: reader@example.com"));
+        assert!(contents.contains("From: newsletter@localhost"));
+        assert!(contents.contains("Subject: Confirm your subscription"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_recipient_writes_nothing() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_service = FileEmailService::new(FileEmailConfig {
+            directory: directory.clone(),
+            default_sender: "newsletter@localhost".into(),
+        })
+        .unwrap();
+
+        let result = email_service.send(email("not an address")).await;
+
+        assert!(matches!(result, Err(EmailError::InvalidAddress(_))));
+        assert_eq!(0, std::fs::read_dir(&directory).unwrap().count());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_directory_that_cannot_be_created_is_an_error() {
+        let file = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::write(&file, "").unwrap();
+
+        let result = FileEmailService::new(FileEmailConfig {
+            directory: file.join("emails"),
+            default_sender: "newsletter@localhost".into(),
+        });
+
+        assert!(result
+            .unwrap_err()
+            .starts_with("Error creating email directory"));
+
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! src/email/http.rs
+
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use secrecy::ExposeSecret;
+use serde::Serialize;
+
+use crate::config::HttpEmailConfig;
+use crate::email::{Email, EmailError, EmailService};
+
+/// Sends email through a Postmark-compatible HTTP API.
+///
+/// Each email is a `POST {base_url}/email` authenticated with the
+/// `X-Postmark-Server-Token` header.
+#[derive(Debug)]
+pub struct HttpEmailService {
+    config: HttpEmailConfig,
+    http_client: Client,
+}
+
+impl HttpEmailService {
+    /// Fails if the HTTP client cannot be set up, e.g. because no TLS
+    /// backend is available.
+    pub fn new(config: HttpEmailConfig) -> Result<Self, reqwest::Error> {
+        let http_client = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+
+        Ok(Self {
+            config,
+            http_client,
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    reply_to: &'a str,
+    headers: Vec<EmailHeader<'a>>,
+    message_stream: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[async_trait]
+impl EmailService for HttpEmailService {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError> {
+        let from = if email.from.is_empty() {
+            &self.config.default_sender
+        } else {
+            email.from
+        };
+        let request = SendEmailRequest {
+            from,
+            to: email.to,
+            subject: email.subject,
+            html_body: email.html,
+            text_body: email.plaintext,
+            reply_to: email.reply_to,
+            headers: email
+                .headers
+                .iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect(),
+            message_stream: "outbound",
+        };
+
+        let response = self
+            .http_client
+            .post(format!(
+                "{}/email",
+                self.config.base_url.trim_end_matches('/')
+            ))
+            .header(
+                "X-Postmark-Server-Token",
+                self.config.api_token.expose_secret(),
+            )
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let code = Some(status.as_u16());
+        let message = format!("Email API responded with {}", body);
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            // A wrong or revoked api_token; the email itself is fine and can go
+            // out once the configuration is fixed.
+            Err(EmailError::Transport {
+                code,
+                message: format!("{}, check email.http.api_token", message),
+            })
+        } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Err(EmailError::Transport { code, message })
+        } else {
+            Err(EmailError::Rejected { code, message })
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            EmailError::Timeout(e.to_string())
+        } else if e.is_builder() {
+            EmailError::Build(e.to_string())
+        } else {
+            EmailError::Transport {
+                code: e.status().map(|status| status.as_u16()),
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use secrecy::Secret;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    use crate::config::HttpEmailConfig;
+    use crate::email::{Email, EmailError, EmailService, HttpEmailService};
+
+    fn email_service(base_url: String) -> HttpEmailService {
+        HttpEmailService::new(HttpEmailConfig {
+            base_url,
+            api_token: Secret::new("server-token".into()),
+            default_sender: "newsletter@example.com".into(),
+            timeout: Duration::from_millis(200),
+            connect_timeout: Duration::from_millis(200),
+        })
+        .unwrap()
+    }
+
+    fn email() -> Email<'static> {
+        Email {
+            to: "reader@example.com",
+            html: "<p>Hello</p>",
+            from: "",
+            subject: "Hello",
+            reply_to: "",
+            plaintext: "Hello",
+            headers: &[("List-Unsubscribe", "<https://example.com/unsubscribe>")],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_posts_email_to_api() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .and(header("X-Postmark-Server-Token", "server-token"))
+            .and(header("Content-Type", "application/json"))
+            .and(|request: &Request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                body["From"] == "newsletter@example.com"
+                    && body["To"] == "reader@example.com"
+                    && body["HtmlBody"] == "<p>Hello</p>"
+                    && body["TextBody"] == "Hello"
+                    && body["Headers"][0]["Name"] == "List-Unsubscribe"
+                    && body.get("ReplyTo").is_none()
+            })
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_service(mock_server.uri()).send(email()).await;
+
+        assert_eq!(Ok(()), result);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_throttling_and_bad_credentials_are_transport_errors() {
+        for status in [401, 403, 429, 500, 503] {
+            let mock_server = MockServer::start().await;
+            Mock::given(header_exists("X-Postmark-Server-Token"))
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&mock_server)
+                .await;
+
+            let result = email_service(mock_server.uri()).send(email()).await;
+
+            assert!(
+                matches!(result, Err(EmailError::Transport { code: Some(code), .. }) if code == status),
+                "{}",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_requests_are_rejected() {
+        for status in [400, 422] {
+            let mock_server = MockServer::start().await;
+            Mock::given(header_exists("X-Postmark-Server-Token"))
+                .respond_with(ResponseTemplate::new(status).set_body_string(r#"{"ErrorCode":300}"#))
+                .mount(&mock_server)
+                .await;
+
+            let result = email_service(mock_server.uri()).send(email()).await;
+
+            assert!(
+                matches!(&result, Err(EmailError::Rejected { code: Some(code), message }) if *code == status && message.contains("ErrorCode")),
+                "{}",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_responses_time_out() {
+        let mock_server = MockServer::start().await;
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&mock_server)
+            .await;
+
+        let result = email_service(mock_server.uri()).send(email()).await;
+
+        assert!(matches!(result, Err(EmailError::Timeout(_))));
+    }
+}
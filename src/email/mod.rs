@@ -0,0 +1,189 @@
+//! src/email/mod.rs
+
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+use crate::config::EmailConfig;
+
+mod file;
+mod http;
+mod smtp;
+mod stdout;
+
+pub use file::FileEmailService;
+pub use http::HttpEmailService;
+pub use smtp::EmailServiceImpl;
+pub use stdout::StdoutEmailService;
+
+/// Represents an email message.
+///
+/// This struct includes all the common fields required to construct an email,
+/// including the recipient, sender, subject, and the body of the message, which
+/// can be provided in both HTML and plaintext formats for compatibility with
+/// different email clients.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use zero2prod::email::Email;
+///
+/// let email = Email {
+///     to: "recipient@example.com",
+///     from: "sender@example.com",
+///     subject: "Greetings!",
+///     reply_to: "no-reply@example.com",
+///     html: "<h1>Hello</h1><p>How are you?</p>",
+///     plaintext: "Hello\nHow are you?",
+///     headers: &[("List-Unsubscribe", "<https://example.com/unsubscribe>")],
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct Email<'a> {
+    /// The recipient's email address.
+    pub to: &'a str,
+    /// The HTML content of the email message. This field allows the inclusion of
+    /// HTML tags for formatting purposes.
+    pub html: &'a str,
+    /// The sender's email address.
+    pub from: &'a str,
+    /// The subject of the email message.
+    pub subject: &'a str,
+    /// The email address for reply-to field, which indicates where replies to the
+    /// email should be sent.
+    pub reply_to: &'a str,
+    /// The plaintext content of the email message. This field is used for email
+    /// clients that do not support HTML content or as a fallback.
+    pub plaintext: &'a str,
+    /// Extra headers as `(name, value)` pairs, e.g. `List-Unsubscribe`.
+    pub headers: &'a [(&'a str, &'a str)],
+}
+
+/// Why an email could not be sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailError {
+    /// A recipient, sender or reply-to address is not a valid mailbox.
+    InvalidAddress(String),
+    /// The message could not be assembled, e.g. because of a malformed
+    /// header.
+    Build(String),
+    /// The provider could not be reached, asked us to try again later or
+    /// refused our credentials. `code` is the SMTP reply code or HTTP status,
+    /// if the provider answered.
+    Transport { code: Option<u16>, message: String },
+    /// The provider did not answer in time.
+    Timeout(String),
+    /// The provider refused the email, e.g. with a 5xx SMTP reply or a 4xx
+    /// API response other than 401, 403 and 429.
+    Rejected { code: Option<u16>, message: String },
+}
+
+impl EmailError {
+    /// Whether the send is worth retrying later. Only failures to reach or
+    /// authenticate with the provider may clear up without changing the email.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EmailError::Transport { .. } | EmailError::Timeout(_))
+    }
+
+    /// The response status for a request that failed because of this error.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            EmailError::InvalidAddress(_) => StatusCode::BAD_REQUEST,
+            EmailError::Build(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            EmailError::Transport { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            EmailError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            EmailError::Rejected { .. } => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl std::fmt::Display for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmailError::InvalidAddress(e) => write!(f, "Invalid address: {}", e),
+            EmailError::Build(e) => write!(f, "Error building email: {}", e),
+            EmailError::Transport {
+                code: Some(code),
+                message,
+            } => write!(f, "Transport error: {} {}", code, message),
+            EmailError::Transport {
+                code: None,
+                message,
+            } => write!(f, "Transport error: {}", message),
+            EmailError::Timeout(e) => write!(f, "Timed out: {}", e),
+            EmailError::Rejected {
+                code: Some(code),
+                message,
+            } => write!(f, "Rejected: {} {}", code, message),
+            EmailError::Rejected {
+                code: None,
+                message,
+            } => write!(f, "Rejected: {}", message),
+        }
+    }
+}
+
+#[async_trait]
+pub trait EmailService {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError>;
+}
+
+/// Builds the service for the provider selected in `config`, or explains why
+/// the provider cannot be set up.
+pub fn email_service(config: &EmailConfig) -> Result<Arc<dyn EmailService + Send + Sync>, String> {
+    Ok(match config {
+        EmailConfig::Smtp(config) => Arc::new(EmailServiceImpl::new(config.clone())?),
+        EmailConfig::Http(config) => Arc::new(
+            HttpEmailService::new(config.clone())
+                .map_err(|e| format!("Error setting up HTTP email client: {}", e))?,
+        ),
+        EmailConfig::File(config) => Arc::new(FileEmailService::new(config.clone())?),
+        EmailConfig::Stdout(config) => Arc::new(StdoutEmailService::new(config.clone())),
+    })
+}
+
+/// Assembles `email` into a MIME message, sent from `default_sender` unless
+/// it names its own sender.
+fn build_message(email: &Email<'_>, default_sender: &str) -> Result<Message, EmailError> {
+    let to = parse_mailbox(email.to)?;
+    let from = if email.from.is_empty() {
+        parse_mailbox(default_sender)?
+    } else {
+        parse_mailbox(email.from)?
+    };
+
+    let mut message_builder = Message::builder().from(from).to(to).subject(email.subject);
+
+    if !email.reply_to.is_empty() {
+        message_builder = message_builder.reply_to(parse_mailbox(email.reply_to)?);
+    }
+
+    let mut message = message_builder
+        .multipart(MultiPart::alternative_plain_html(
+            email.plaintext.to_string(),
+            email.html.to_string(),
+        ))
+        .map_err(|e| EmailError::Build(e.to_string()))?;
+
+    for (name, value) in email.headers {
+        let name = HeaderName::new_from_ascii(name.to_string())
+            .map_err(|e| EmailError::Build(format!("Invalid header {}: {}", name, e)))?;
+        message
+            .headers_mut()
+            .insert_raw(HeaderValue::new(name, value.to_string()));
+    }
+
+    Ok(message)
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, EmailError> {
+    address
+        .parse()
+        .map_err(|e| EmailError::InvalidAddress(format!("{}: {}", address, e)))
+}
@@ -0,0 +1,116 @@
+//! src/email/smtp.rs
+
+use async_trait::async_trait;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use crate::config::SmtpConfig;
+use crate::email::{build_message, Email, EmailError, EmailService};
+
+/// Sends email over SMTP without blocking the runtime, reusing up to
+/// `SmtpConfig::pool_size` connections.
+#[derive(Debug)]
+pub struct EmailServiceImpl {
+    config: SmtpConfig,
+    smtp_transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl EmailServiceImpl {
+    /// Fails if the TLS parameters for `SmtpConfig::host` cannot be set up.
+    pub fn new(config: SmtpConfig) -> Result<Self, String> {
+        let tls_parameters = TlsParameters::builder(config.host.clone())
+            .build()
+            .map_err(|e| format!("Error setting up TLS for SMTP host {}: {}", config.host, e))?;
+
+        let creds = Credentials::new(config.user.clone(), config.password.expose_secret().clone());
+
+        let smtp_transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+            .map_err(|e| format!("Error setting up SMTP relay {}: {}", config.host, e))?
+            .tls(Tls::Required(tls_parameters))
+            .port(config.port)
+            .credentials(creds)
+            .pool_config(PoolConfig::new().max_size(config.pool_size))
+            .build();
+
+        Ok(Self {
+            config,
+            smtp_transport,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailService for EmailServiceImpl {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError> {
+        let message = build_message(&email, &self.config.default_sender)?;
+        self.smtp_transport.send(message).await?;
+        Ok(())
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for EmailError {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        let code = e
+            .status()
+            .and_then(|code| code.to_string().parse::<u16>().ok());
+        let message = e.to_string();
+        if e.is_timeout() {
+            EmailError::Timeout(message)
+        } else if e.is_permanent() {
+            EmailError::Rejected { code, message }
+        } else {
+            EmailError::Transport { code, message }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use crate::config::SmtpConfig;
+    use crate::email::{Email, EmailError, EmailService, EmailServiceImpl};
+
+    fn email_service() -> EmailServiceImpl {
+        EmailServiceImpl::new(SmtpConfig {
+            host: "localhost".into(),
+            port: 2525,
+            user: "user".into(),
+            password: Secret::new("password".into()),
+            default_sender: "newsletter@example.com".into(),
+            pool_size: 10,
+        })
+        .unwrap()
+    }
+
+    fn email<'a>(to: &'a str, reply_to: &'a str) -> Email<'a> {
+        Email {
+            to,
+            html: "<p>Hello</p>",
+            from: "",
+            subject: "Hello",
+            reply_to,
+            plaintext: "Hello",
+            headers: &[],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_recipient_is_an_error() {
+        let result = email_service().send(email("not an address", "")).await;
+
+        assert!(matches!(result, Err(EmailError::InvalidAddress(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_reply_to_is_an_error() {
+        let result = email_service()
+            .send(email("reader@example.com", "not an address"))
+            .await;
+
+        assert!(matches!(result, Err(EmailError::InvalidAddress(_))));
+    }
+}
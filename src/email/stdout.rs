@@ -0,0 +1,89 @@
+//! src/email/stdout.rs
+
+use async_trait::async_trait;
+
+use crate::config::StdoutEmailConfig;
+use crate::email::{build_message, Email, EmailError, EmailService};
+
+/// Prints every email to stdout in a readable form, plaintext part only.
+#[derive(Debug)]
+pub struct StdoutEmailService {
+    config: StdoutEmailConfig,
+}
+
+impl StdoutEmailService {
+    pub fn new(config: StdoutEmailConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl EmailService for StdoutEmailService {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError> {
+        // Rejects the same emails the real providers would.
+        build_message(&email, &self.config.default_sender)?;
+
+        println!("{}", format_email(&email, &self.config.default_sender));
+        Ok(())
+    }
+}
+
+fn format_email(email: &Email<'_>, default_sender: &str) -> String {
+    let from = if email.from.is_empty() {
+        default_sender
+    } else {
+        email.from
+    };
+
+    let mut formatted = format!(
+        "---------- email ----------\nFrom: {}\nTo: {}\n",
+        from, email.to
+    );
+    if !email.reply_to.is_empty() {
+        formatted.push_str(&format!("Reply-To: {}\n", email.reply_to));
+    }
+    formatted.push_str(&format!("Subject: {}\n", email.subject));
+    for (name, value) in email.headers {
+        formatted.push_str(&format!("{}: {}\n", name, value));
+    }
+    formatted.push_str(&format!(
+        "\n{}\n---------------------------",
+        email.plaintext.trim_end()
+    ));
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email::stdout::format_email;
+    use crate::email::Email;
+
+    #[test]
+    fn test_format_email() {
+        let email = Email {
+            to: "reader@example.com",
+            html: "<p>Hello</p>",
+            from: "",
+            subject: "Hello",
+            reply_to: "editor@example.com",
+            plaintext: "Hello\n",
+            headers: &[("List-Unsubscribe", "<https://example.com/unsubscribe>")],
+        };
+
+        let formatted = format_email(&email, "newsletter@localhost");
+
+        assert_eq!(
+            "---------- email ----------\n\
+             From: newsletter@localhost\n\
+             To: reader@example.com\n\
+             Reply-To: editor@example.com\n\
+             Subject: Hello\n\
+             List-Unsubscribe: <https://example.com/unsubscribe>\n\
+             \n\
+             Hello\n\
+             ---------------------------",
+            formatted
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! src/idempotency/idempotency_error.rs
+
+use actix_web::{error::ResponseError, HttpResponse};
+use std::fmt::{Display, Error, Formatter};
+
+#[derive(Debug)]
+pub enum IdempotencyError {
+    InvalidKey(String),
+    DatabaseError(sqlx::Error),
+    UnexpectedError(String),
+}
+
+impl Display for IdempotencyError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            IdempotencyError::InvalidKey(e) => write!(f, "Invalid idempotency key: {}", e),
+            IdempotencyError::DatabaseError(e) => write!(f, "Database Error: {}", e),
+            IdempotencyError::UnexpectedError(e) => write!(f, "Unexpected error: {}", e),
+        }
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            IdempotencyError::InvalidKey(ref message) => {
+                HttpResponse::BadRequest().json(format!("Invalid idempotency key: {}", message))
+            }
+            IdempotencyError::DatabaseError(ref error) => {
+                HttpResponse::InternalServerError().json(error.to_string())
+            }
+            IdempotencyError::UnexpectedError(ref message) => {
+                HttpResponse::InternalServerError().json(message)
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! src/idempotency/key.rs
+
+use crate::idempotency::IdempotencyError;
+
+/// A client supplied key identifying one logical request across retries.
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    const MAX_LENGTH: usize = 50;
+
+    pub fn parse(s: String) -> Result<IdempotencyKey, IdempotencyError> {
+        if s.trim().is_empty() {
+            return Err(IdempotencyError::InvalidKey("Empty key".into()));
+        }
+
+        if s.len() > Self::MAX_LENGTH {
+            return Err(IdempotencyError::InvalidKey(format!(
+                "Length greater than {}",
+                Self::MAX_LENGTH
+            )));
+        }
+
+        Ok(IdempotencyKey(s))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        let IdempotencyKey(inner) = self;
+        inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idempotency::IdempotencyKey;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn test_empty_key() {
+        assert_err!(IdempotencyKey::parse(" ".into()));
+    }
+
+    #[test]
+    fn test_long_key() {
+        assert_err!(IdempotencyKey::parse("a".repeat(51)));
+    }
+
+    #[test]
+    fn test_valid_key() {
+        assert_ok!(IdempotencyKey::parse(uuid::Uuid::new_v4().to_string()));
+    }
+}
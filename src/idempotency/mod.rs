@@ -0,0 +1,9 @@
+//! src/idempotency/mod.rs
+
+mod idempotency_error;
+mod key;
+mod persistence;
+
+pub use idempotency_error::IdempotencyError;
+pub use key::IdempotencyKey;
+pub use persistence::{save_response, try_processing, NextAction};
@@ -0,0 +1,164 @@
+//! src/idempotency/persistence.rs
+
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use chrono::Utc;
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::idempotency::{IdempotencyError, IdempotencyKey};
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+/// What the caller should do after claiming an idempotency key.
+pub enum NextAction {
+    /// The key is new; process the request inside this transaction and pass
+    /// it to [`save_response`] once done.
+    StartProcessing(Box<Transaction<'static, Postgres>>),
+    /// The key was seen before; replay this response without side effects.
+    ReturnSavedResponse(HttpResponse),
+}
+
+/// Claims `idempotency_key` for `user_id`.
+///
+/// The claim is an `INSERT` that stays uncommitted until [`save_response`]
+/// runs, so a concurrent request with the same key blocks on the primary key
+/// until the first one finishes and then replays its response.
+pub async fn try_processing(
+    pool: &Pool<Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(IdempotencyError::DatabaseError)?;
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("claim idempotency key query"))
+    .await
+    .map_err(IdempotencyError::DatabaseError)?
+    .rows_affected();
+
+    if inserted > 0 {
+        return Ok(NextAction::StartProcessing(Box::new(transaction)));
+    }
+
+    let saved_response = get_saved_response(pool, idempotency_key, user_id)
+        .await?
+        .ok_or_else(|| {
+            IdempotencyError::UnexpectedError("Expected a saved response, found none".into())
+        })?;
+
+    Ok(NextAction::ReturnSavedResponse(saved_response))
+}
+
+async fn get_saved_response(
+    pool: &Pool<Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<HttpResponse>, IdempotencyError> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .instrument(tracing::info_span!("get saved response query"))
+    .await
+    .map_err(IdempotencyError::DatabaseError)?;
+
+    let Some(saved_response) = saved_response else {
+        return Ok(None);
+    };
+
+    let status_code = StatusCode::from_u16(saved_response.response_status_code as u16)
+        .map_err(|e| IdempotencyError::UnexpectedError(e.to_string()))?;
+
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in saved_response.response_headers {
+        response.append_header((name, value));
+    }
+
+    Ok(Some(response.body(saved_response.response_body)))
+}
+
+/// Stores `http_response` against the key claimed by [`try_processing`] and
+/// commits the caller's transaction.
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, IdempotencyError> {
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| IdempotencyError::UnexpectedError(e.to_string()))?;
+
+    let status_code = response_head.status().as_u16() as i16;
+    let headers: Vec<HeaderPairRecord> = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect();
+
+    sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("save response query"))
+    .await
+    .map_err(IdempotencyError::DatabaseError)?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(IdempotencyError::DatabaseError)?;
+
+    Ok(response_head.set_body(body).map_into_boxed_body())
+}
@@ -3,7 +3,18 @@
 pub mod app;
 pub mod auth;
 pub mod config;
+pub mod delivery;
 pub mod domain;
 pub mod email;
+pub mod idempotency;
+pub mod migrations;
+pub mod outbox;
 pub mod routes;
+pub mod session;
+pub mod subscription_tokens;
+pub mod subscriptions;
 pub mod templates;
+pub mod time;
+pub mod two_factor;
+pub mod unsubscribe;
+pub mod users;
@@ -1,18 +1,106 @@
-use std::sync::Arc;
+use std::io::{self, BufRead, IsTerminal, Write};
 
-use zero2prod::config::Config;
+use clap::{Parser, Subcommand};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres};
 
 use zero2prod::app::Application;
-use zero2prod::email::EmailServiceImpl;
+use zero2prod::config::{Config, PasswordPolicyConfig, Scope};
+use zero2prod::domain::password::NewPassword;
+use zero2prod::email;
+use zero2prod::migrations;
+use zero2prod::users;
+
+#[derive(Parser)]
+#[command(version, about = "Newsletter API server")]
+struct Cli {
+    /// Defaults to `serve`.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server and background workers.
+    Serve {
+        /// Do not apply pending migrations at startup, whatever
+        /// `database.migrate_on_startup` says.
+        #[arg(long)]
+        no_migrate: bool,
+    },
+    /// Manage database migrations.
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Manage admin users.
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply all pending migrations.
+    Run,
+    /// Revert the most recently applied migration.
+    Revert,
+    /// List migrations and whether they have been applied.
+    Status,
+}
+
+/// Passwords are read from the terminal without echo, or from the first line
+/// of stdin when it is not a terminal.
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create an admin user.
+    Create {
+        username: String,
+        /// Address password reset links are sent to.
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Replace a user's password and log them out everywhere.
+    SetPassword { username: String },
+    /// Set the address password reset links are sent to; omit it to remove
+    /// the address.
+    SetEmail {
+        username: String,
+        email: Option<String>,
+    },
+    /// Stop a user from logging in and log them out everywhere.
+    Disable { username: String },
+    /// Turn off a user's two-factor authentication, e.g. when they lost
+    /// their authenticator app and recovery codes.
+    ResetTwoFactor { username: String },
+    /// List users.
+    List,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
     tracing_subscriber::fmt::init();
 
-    let config = Config::new();
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Serve { no_migrate: false }) {
+        Command::Serve { no_migrate } => {
+            let mut config = Config::load(Scope::Server)?;
+            if no_migrate {
+                config.db_config.migrate_on_startup = false;
+            }
+            serve(config).await
+        }
+        Command::Migrate { command } => migrate(Config::load(Scope::Database)?, command).await,
+        Command::User { command } => user(Config::load(Scope::Users)?, command).await,
+    }
+}
+
+async fn serve(config: Config) -> Result<(), String> {
     let addr = format!("[::]:{}", config.port);
 
-    let email_service = Arc::new(EmailServiceImpl::new(config.smtp_config.clone()));
+    let email_service = email::email_service(&config.email_config)?;
 
     let app = Application::build(&config, addr, email_service).await?;
     app.run_until_stopped()
@@ -21,3 +109,134 @@ async fn main() -> Result<(), String> {
 
     Ok(())
 }
+
+async fn connect(config: &Config) -> Result<Pool<Postgres>, String> {
+    config
+        .db_config
+        .pool_options()
+        .connect_with(config.db_config.connect_options())
+        .await
+        .map_err(|e| format!("Error connecting to DB: {}", e))
+}
+
+async fn migrate(config: Config, command: MigrateCommand) -> Result<(), String> {
+    let pool = connect(&config).await?;
+
+    match command {
+        MigrateCommand::Run => {
+            migrations::run(&pool).await?;
+            println!("Database is up to date");
+        }
+        MigrateCommand::Revert => match migrations::revert(&pool).await? {
+            Some(version) => println!("Reverted migration {}", version),
+            None => println!("No migration to revert"),
+        },
+        MigrateCommand::Status => {
+            for migration in migrations::status(&pool).await? {
+                let state = match (migration.applied, migration.checksum_mismatch) {
+                    (true, true) => "changed",
+                    (true, false) => "applied",
+                    (false, _) => "pending",
+                };
+                println!(
+                    "{:>14} {:<8} {}",
+                    migration.version, state, migration.description
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn user(config: Config, command: UserCommand) -> Result<(), String> {
+    let pool = connect(&config).await?;
+    let password_hash_config = &config.password_hash_config;
+    let password_policy = &config.password_policy_config;
+
+    match command {
+        UserCommand::Create { username, email } => {
+            let password = read_new_password(password_policy)?;
+            let user_id = users::create(
+                &pool,
+                &username,
+                email.as_deref(),
+                password.as_ref(),
+                password_hash_config,
+            )
+            .await?;
+            println!("Created user {} ({})", username, user_id);
+        }
+        UserCommand::SetPassword { username } => {
+            let password = read_new_password(password_policy)?;
+            users::set_password(&pool, &username, password.as_ref(), password_hash_config).await?;
+            println!("Changed the password of {}", username);
+        }
+        UserCommand::SetEmail { username, email } => {
+            users::set_email(&pool, &username, email.as_deref()).await?;
+            match email {
+                Some(email) => println!("Set the email address of {} to {}", username, email),
+                None => println!("Removed the email address of {}", username),
+            }
+        }
+        UserCommand::Disable { username } => {
+            users::disable(&pool, &username).await?;
+            println!("Disabled {}", username);
+        }
+        UserCommand::ResetTwoFactor { username } => {
+            users::reset_two_factor(&pool, &username).await?;
+            println!("Turned off two-factor authentication for {}", username);
+        }
+        UserCommand::List => {
+            for user in users::list(&pool).await? {
+                let state = match user.disabled_at {
+                    Some(disabled_at) => format!("disabled {}", disabled_at.format("%Y-%m-%d")),
+                    None => "active".to_string(),
+                };
+                println!(
+                    "{} {:<20} {:<30} {}",
+                    user.id,
+                    user.username,
+                    user.email.unwrap_or_default(),
+                    state
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prompts twice for a password on a terminal, or reads one line from stdin,
+/// and checks it against the password policy.
+fn read_new_password(policy: &PasswordPolicyConfig) -> Result<NewPassword, String> {
+    let password = read_password()?;
+    NewPassword::parse(password, policy).map_err(|e| e.to_string())
+}
+
+fn read_password() -> Result<Secret<String>, String> {
+    if !io::stdin().is_terminal() {
+        let mut password = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut password)
+            .map_err(|e| format!("Error reading password: {}", e))?;
+        let password = password.trim_end_matches(['\r', '\n']).to_string();
+        return Ok(Secret::new(password));
+    }
+
+    let password = prompt_password("Password: ")?;
+    let confirmation = prompt_password("Repeat password: ")?;
+    if password.expose_secret() != confirmation.expose_secret() {
+        return Err("Passwords do not match".into());
+    }
+    Ok(password)
+}
+
+fn prompt_password(prompt: &str) -> Result<Secret<String>, String> {
+    eprint!("{}", prompt);
+    io::stderr().flush().ok();
+    rpassword::read_password()
+        .map(Secret::new)
+        .map_err(|e| format!("Error reading password: {}", e))
+}
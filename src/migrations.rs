@@ -0,0 +1,149 @@
+//! src/migrations.rs
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migrator};
+use sqlx::{Pool, Postgres};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::delivery::sleep_unless_cancelled;
+
+/// The migrations in `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// How long to wait before retrying migrations against a database that is
+/// not up yet.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Where a migration stands against the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    /// Applied, but from a file that has been edited since.
+    pub checksum_mismatch: bool,
+}
+
+/// Applies all pending migrations.
+pub async fn run(pool: &Pool<Postgres>) -> Result<(), String> {
+    MIGRATOR
+        .run(pool)
+        .await
+        .map_err(|e| format!("Error migrating db {}", e))
+}
+
+/// Like [`run`], but waits for a database that cannot be reached yet,
+/// retrying until it can or `shutdown` is cancelled. Any other error, e.g. a
+/// migration that fails or was edited after being applied, is returned right
+/// away since retrying would not help.
+pub async fn run_when_reachable(
+    pool: &Pool<Postgres>,
+    shutdown: &CancellationToken,
+) -> Result<(), String> {
+    loop {
+        match MIGRATOR.run(pool).await {
+            Ok(()) => return Ok(()),
+            Err(e) if is_unreachable(&e) && !shutdown.is_cancelled() => {
+                warn!(
+                    "Error connecting to db to migrate it, retrying in {:?}: {}",
+                    RETRY_INTERVAL, e
+                );
+                sleep_unless_cancelled(RETRY_INTERVAL, shutdown).await;
+            }
+            Err(e) => return Err(format!("Error migrating db {}", e)),
+        }
+    }
+}
+
+/// Whether `error` means the database is down or still starting up.
+fn is_unreachable(error: &MigrateError) -> bool {
+    let MigrateError::Execute(error) = error else {
+        return false;
+    };
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // cannot_connect_now, sent while the server starts up
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
+/// Reverts the most recently applied migration and returns its version, or
+/// `None` if no migration is applied.
+///
+/// Only migrations written as `.up.sql`/`.down.sql` pairs can be reverted.
+pub async fn revert(pool: &Pool<Postgres>) -> Result<Option<i64>, String> {
+    let mut applied: Vec<i64> = applied_migrations(pool)
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    applied.sort_unstable();
+    let Some(latest) = applied.pop() else {
+        return Ok(None);
+    };
+
+    let reversible = MIGRATOR
+        .iter()
+        .any(|m| m.version == latest && m.migration_type.is_down_migration());
+    if !reversible {
+        let description = MIGRATOR
+            .iter()
+            .find(|m| m.version == latest)
+            .map(|m| m.description.to_string())
+            .unwrap_or_default();
+        return Err(format!(
+            "Migration {} ({}) has no down script and cannot be reverted",
+            latest, description
+        ));
+    }
+
+    let target = applied.last().copied().unwrap_or(0);
+    MIGRATOR
+        .undo(pool, target)
+        .await
+        .map_err(|e| format!("Error reverting migration {}: {}", latest, e))?;
+
+    Ok(Some(latest))
+}
+
+/// Lists every known migration in order, with whether it has been applied.
+pub async fn status(pool: &Pool<Postgres>) -> Result<Vec<MigrationStatus>, String> {
+    let applied: HashMap<_, _> = applied_migrations(pool)
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum))
+        .collect();
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| {
+            let checksum = applied.get(&m.version);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: checksum.is_some(),
+                checksum_mismatch: checksum.is_some_and(|checksum| *checksum != m.checksum),
+            }
+        })
+        .collect())
+}
+
+async fn applied_migrations(pool: &Pool<Postgres>) -> Result<Vec<AppliedMigration>, String> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .map_err(|e| format!("Error connecting to DB: {}", e))?;
+    connection
+        .ensure_migrations_table()
+        .await
+        .map_err(|e| format!("Error reading migrations: {}", e))?;
+    connection
+        .list_applied_migrations()
+        .await
+        .map_err(|e| format!("Error reading migrations: {}", e))
+}
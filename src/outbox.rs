@@ -0,0 +1,242 @@
+//! src/outbox.rs
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, instrument, warn, Instrument};
+use uuid::Uuid;
+
+use crate::config::DeliveryConfig;
+use crate::delivery::{retry_delay, sleep_unless_cancelled, ExecutionOutcome};
+use crate::email::{Email, EmailError, EmailService};
+
+/// How long the idle relay waits before polling the outbox again.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Lifecycle of an `email_outbox` row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "outbox_status", rename_all = "lowercase")]
+pub enum OutboxStatus {
+    /// Committed, not attempted yet.
+    Pending,
+    /// Failed transiently, due again at `execute_after`.
+    Retrying,
+    Sent,
+    /// Failed permanently or ran out of retries; see `last_error`.
+    Failed,
+}
+
+/// An [`EmailService`] that defers delivery to the outbox relay.
+///
+/// Sending inserts the email into `email_outbox` as part of the caller's
+/// transaction, so it goes out if and only if that transaction commits, and
+/// delivery failures are retried by the relay instead of failing the request.
+///
+/// Newsletter issues do not go through here: `issue_delivery_queue` already
+/// serves as their outbox, committed together with the issue.
+pub struct OutboxEmailService<'a> {
+    transaction: tokio::sync::Mutex<&'a mut Transaction<'static, Postgres>>,
+}
+
+impl<'a> OutboxEmailService<'a> {
+    pub fn new(transaction: &'a mut Transaction<'static, Postgres>) -> Self {
+        Self {
+            transaction: tokio::sync::Mutex::new(transaction),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailService for OutboxEmailService<'_> {
+    async fn send(&self, email: Email<'_>) -> Result<(), EmailError> {
+        let headers: Vec<(&str, &str)> = email.headers.to_vec();
+        let mut transaction = self.transaction.lock().await;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (
+                email_id, recipient, sender, reply_to, subject,
+                html_content, text_content, headers, status, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            Uuid::new_v4(),
+            email.to,
+            email.from,
+            email.reply_to,
+            email.subject,
+            email.html,
+            email.plaintext,
+            Json(headers) as _,
+            OutboxStatus::Pending as OutboxStatus,
+            Utc::now()
+        )
+        .execute(&mut ***transaction)
+        .instrument(tracing::info_span!("enqueue outbox email query"))
+        .await
+        .map_err(|e| EmailError::Transport {
+            code: None,
+            message: format!("Error saving email to outbox: {}", e),
+        })?;
+
+        Ok(())
+    }
+}
+
+/// An email due for (re)delivery, claimed by the relay.
+struct OutboxEmail {
+    email_id: Uuid,
+    recipient: String,
+    sender: String,
+    reply_to: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    headers: Json<Vec<(String, String)>>,
+    n_retries: i32,
+}
+
+/// Spawns the task relaying committed outbox emails to `email_service` until
+/// `shutdown` is cancelled, after settling the email in progress.
+pub fn spawn_relay(
+    config: &DeliveryConfig,
+    pool: Pool<Postgres>,
+    email_service: Arc<dyn EmailService + Send + Sync>,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    let config = config.clone();
+    tokio::spawn(async move {
+        while !shutdown.is_cancelled() {
+            match try_relay_email(&pool, email_service.as_ref(), &config).await {
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    sleep_unless_cancelled(POLL_INTERVAL, &shutdown).await
+                }
+                Err(e) => {
+                    error!("Error relaying outbox email: {}", e);
+                    sleep_unless_cancelled(POLL_INTERVAL, &shutdown).await;
+                }
+            }
+        }
+    })
+}
+
+/// Claims one due outbox email and hands it to `email_service`.
+///
+/// Delivered emails are marked `sent`. Transient failures are marked
+/// `retrying` and rescheduled with the same backoff as newsletter
+/// deliveries until `max_retries` is exhausted; after that, or on a
+/// permanent failure, the email is marked `failed` with the error kept in
+/// `last_error`.
+#[instrument(skip_all, fields(email_id = tracing::field::Empty))]
+pub async fn try_relay_email(
+    pool: &Pool<Postgres>,
+    email_service: &(dyn EmailService + Send + Sync),
+    config: &DeliveryConfig,
+) -> Result<ExecutionOutcome, String> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+    let outbox_email = sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT
+            email_id, recipient, sender, reply_to, subject, html_content, text_content,
+            headers as "headers: Json<Vec<(String, String)>>",
+            n_retries
+        FROM email_outbox
+        WHERE status IN ('pending', 'retrying') AND execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .instrument(tracing::info_span!("dequeue outbox email query"))
+    .await
+    .map_err(|e| format!("Error dequeuing outbox email: {}", e))?;
+
+    let Some(outbox_email) = outbox_email else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    tracing::Span::current().record("email_id", tracing::field::display(outbox_email.email_id));
+
+    let headers: Vec<(&str, &str)> = outbox_email
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let email = Email {
+        to: &outbox_email.recipient,
+        html: &outbox_email.html_content,
+        from: &outbox_email.sender,
+        subject: &outbox_email.subject,
+        reply_to: &outbox_email.reply_to,
+        plaintext: &outbox_email.text_content,
+        headers: &headers,
+    };
+
+    let (status, execute_after, last_error) = match email_service.send(email).await {
+        Ok(()) => {
+            info!("Relayed outbox email");
+            (OutboxStatus::Sent, None, None)
+        }
+        Err(e) if e.is_transient() && (outbox_email.n_retries as u32) < config.max_retries => {
+            let delay = retry_delay(config.retry_base_delay, outbox_email.n_retries as u32);
+            warn!(
+                "Failed to relay outbox email, retrying in {:?}: {}",
+                delay, e
+            );
+            let execute_after = Utc::now()
+                + chrono::Duration::from_std(delay).map_err(|e| format!("Invalid delay: {}", e))?;
+            (
+                OutboxStatus::Retrying,
+                Some(execute_after),
+                Some(e.to_string()),
+            )
+        }
+        Err(e) => {
+            error!("Failed to relay outbox email, giving up: {}", e);
+            (OutboxStatus::Failed, None, Some(e.to_string()))
+        }
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET
+            status = $2,
+            n_retries = n_retries + $3,
+            execute_after = COALESCE($4, execute_after),
+            last_error = $5,
+            sent_at = $6
+        WHERE email_id = $1
+        "#,
+        outbox_email.email_id,
+        status as OutboxStatus,
+        i32::from(status == OutboxStatus::Retrying),
+        execute_after,
+        last_error,
+        (status == OutboxStatus::Sent).then(Utc::now)
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("update outbox email status query"))
+    .await
+    .map_err(|e| format!("Error updating outbox email: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing outbox email: {}", e))?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
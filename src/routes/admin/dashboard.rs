@@ -0,0 +1,46 @@
+//! src/routes/admin/dashboard.rs
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use askama::Template;
+use sqlx::{Pool, Postgres};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthError, UserId},
+    templates::AdminDashboardTemplate,
+};
+
+#[tracing::instrument(name = "Admin dashboard", skip(pool), fields(user_id = %*user_id))]
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, pool.get_ref()).await?;
+
+    let dashboard_template = AdminDashboardTemplate {
+        username: &username,
+    };
+    let dashboard_rendered = dashboard_template.render().unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(dashboard_rendered))
+}
+
+pub(crate) async fn get_username(
+    user_id: Uuid,
+    pool: &Pool<Postgres>,
+) -> Result<String, AuthError> {
+    let user = sqlx::query!(
+        r#"
+        SELECT username FROM users WHERE id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .instrument(tracing::info_span!("get username query"))
+    .await
+    .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+
+    Ok(user.username)
+}
@@ -0,0 +1,121 @@
+//! src/routes/admin/dead_letters.rs
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::{info, instrument, Instrument};
+use uuid::Uuid;
+
+use crate::{auth::UserId, domain::newsletter::NewsletterError};
+
+#[derive(Serialize)]
+pub struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i32,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayRequest {
+    newsletter_issue_id: Uuid,
+    /// Replays every dead letter of the issue when omitted.
+    subscriber_email: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReplayResponse {
+    replayed: i64,
+    /// Dead letters left in place because the delivery is queued already.
+    already_queued: i64,
+}
+
+#[instrument(
+    name = "List dead-lettered deliveries",
+    skip(pool, user_id),
+    fields(
+        request_id = %Uuid::new_v4(),
+        user_id = %*user_id
+    )
+)]
+pub async fn list_dead_letters(
+    pool: web::Data<Pool<Postgres>>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at
+        FROM issue_delivery_dead_letters
+        ORDER BY failed_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .instrument(tracing::info_span!("list dead letters query"))
+    .await
+    .map_err(NewsletterError::DatabaseError)?;
+
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+#[instrument(
+    name = "Replay dead-lettered deliveries",
+    skip(pool, user_id),
+    fields(
+        request_id = %Uuid::new_v4(),
+        user_id = %*user_id
+    )
+)]
+pub async fn replay_dead_letters(
+    json: web::Json<ReplayRequest>,
+    pool: web::Data<Pool<Postgres>>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Only dead letters that made it into the queue are removed; the others
+    // are kept until the queued delivery has gone through.
+    let counts = sqlx::query!(
+        r#"
+        WITH dead_letters AS (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_dead_letters
+            WHERE newsletter_issue_id = $1
+                AND ($2::TEXT IS NULL OR subscriber_email = $2)
+        ),
+        requeued AS (
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            SELECT newsletter_issue_id, subscriber_email FROM dead_letters
+            ON CONFLICT DO NOTHING
+            RETURNING newsletter_issue_id, subscriber_email
+        ),
+        replayed AS (
+            DELETE FROM issue_delivery_dead_letters d
+            USING requeued r
+            WHERE d.newsletter_issue_id = r.newsletter_issue_id
+                AND d.subscriber_email = r.subscriber_email
+            RETURNING d.subscriber_email
+        )
+        SELECT
+            (SELECT count(*) FROM replayed) AS "replayed!",
+            (SELECT count(*) FROM dead_letters) - (SELECT count(*) FROM replayed)
+                AS "already_queued!"
+        "#,
+        json.newsletter_issue_id,
+        json.subscriber_email
+    )
+    .fetch_one(pool.get_ref())
+    .instrument(tracing::info_span!("replay dead letters query"))
+    .await
+    .map_err(NewsletterError::DatabaseError)?;
+
+    info!(
+        "Requeued {} dead-lettered deliveries, {} were queued already",
+        counts.replayed, counts.already_queued
+    );
+
+    Ok(HttpResponse::Accepted().json(ReplayResponse {
+        replayed: counts.replayed,
+        already_queued: counts.already_queued,
+    }))
+}
@@ -0,0 +1,13 @@
+//! src/routes/admin/logout.rs
+
+use actix_web::{http::header::LOCATION, HttpResponse};
+
+use crate::session::TypedSession;
+
+#[tracing::instrument(name = "Log out", skip(session))]
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    session.log_out();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish()
+}
@@ -0,0 +1,15 @@
+mod dashboard;
+mod dead_letters;
+mod logout;
+mod newsletter;
+mod password;
+mod subscribers;
+mod two_factor;
+
+pub use dashboard::*;
+pub use dead_letters::*;
+pub use logout::*;
+pub use newsletter::*;
+pub use password::*;
+pub use subscribers::*;
+pub use two_factor::*;
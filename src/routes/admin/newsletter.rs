@@ -0,0 +1,84 @@
+//! src/routes/admin/newsletter.rs
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    auth::UserId, domain::newsletter::Newsletter, idempotency::IdempotencyKey,
+    routes::publish_issue, templates::AdminNewsletterTemplate,
+};
+
+#[derive(Deserialize)]
+pub struct NewsletterFormData {
+    subject: String,
+    text: String,
+    html: String,
+    idempotency_key: String,
+}
+
+#[tracing::instrument(
+    name = "Newsletter form",
+    skip(user_id, flash_messages),
+    fields(user_id = %*user_id)
+)]
+pub async fn newsletter_form(
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    let idempotency_key = Uuid::new_v4().to_string();
+    let newsletter_template = AdminNewsletterTemplate {
+        idempotency_key: &idempotency_key,
+        messages: flash_messages.iter().map(|m| m.content()).collect(),
+    };
+    let newsletter_rendered = newsletter_template.render().unwrap();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(newsletter_rendered)
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue from the admin form",
+    skip(form, pool, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn publish_newsletter_form(
+    form: web::Form<NewsletterFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewsletterFormData {
+        subject,
+        text,
+        html,
+        idempotency_key,
+    } = form.0;
+    let idempotency_key = IdempotencyKey::parse(idempotency_key)?;
+    let newsletter = Newsletter {
+        html,
+        text,
+        subject,
+    };
+
+    let response = HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/newsletter"))
+        .finish();
+
+    let response = publish_issue(
+        pool.get_ref(),
+        **user_id,
+        &newsletter,
+        Some(idempotency_key),
+        response,
+    )
+    .await?;
+
+    FlashMessage::info("The newsletter issue has been accepted, emails will go out shortly").send();
+    Ok(response)
+}
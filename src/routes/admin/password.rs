@@ -0,0 +1,120 @@
+//! src/routes/admin/password.rs
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    auth::{validate_credentials, AuthError, Credentials, UserId},
+    config::{PasswordHashConfig, PasswordPolicyConfig},
+    domain::password::{NewPassword, PasswordError},
+    routes::get_username,
+    session::{log_out_other_sessions, TypedSession},
+    templates::AdminPasswordTemplate,
+    users,
+};
+
+#[derive(Deserialize)]
+pub struct ChangePasswordFormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Change password form",
+    skip(user_id, flash_messages),
+    fields(user_id = %*user_id)
+)]
+pub async fn change_password_form(
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    let password_template = AdminPasswordTemplate {
+        messages: flash_messages.iter().map(|m| m.content()).collect(),
+    };
+    let password_rendered = password_template.render().unwrap();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(password_rendered)
+}
+
+/// Changes the password of the logged in user, who must know the current
+/// one, and logs them out of every other session.
+#[tracing::instrument(
+    name = "Change password",
+    skip(form, pool, password_hash_config, password_policy, session, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn change_password(
+    form: web::Form<ChangePasswordFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    password_hash_config: web::Data<PasswordHashConfig>,
+    password_policy: web::Data<PasswordPolicyConfig>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ChangePasswordFormData {
+        current_password,
+        new_password,
+        new_password_check,
+    } = form.0;
+
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Ok(back_with_error(
+            "You entered two different new passwords - the field values must match",
+        ));
+    }
+    if new_password.expose_secret() == current_password.expose_secret() {
+        return Ok(back_with_error(
+            "The new password must differ from the current one",
+        ));
+    }
+    let new_password = match NewPassword::parse(new_password, &password_policy) {
+        Ok(new_password) => new_password,
+        Err(e) => return Ok(back_with_error(&e.to_string())),
+    };
+
+    let username = get_username(**user_id, pool.get_ref()).await?;
+    let credentials = Credentials {
+        username,
+        password: current_password,
+    };
+    match validate_credentials(credentials, pool.get_ref(), &password_hash_config).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials) => {
+            return Ok(back_with_error("The current password is incorrect"));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    users::change_password(
+        pool.get_ref(),
+        **user_id,
+        new_password.as_ref(),
+        &password_hash_config,
+    )
+    .await
+    .map_err(PasswordError::UnexpectedError)?;
+    log_out_other_sessions(pool.get_ref(), &session, **user_id)
+        .await
+        .map_err(PasswordError::DatabaseError)?;
+
+    FlashMessage::info("Your password has been changed").send();
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/password"))
+        .finish())
+}
+
+fn back_with_error(message: &str) -> HttpResponse {
+    FlashMessage::error(message).send();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/password"))
+        .finish()
+}
@@ -0,0 +1,230 @@
+//! src/routes/admin/subscribers.rs
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use askama::Template;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::de::value::StringDeserializer;
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::{instrument, Instrument};
+use uuid::Uuid;
+
+use crate::{
+    auth::UserId,
+    domain::subscriber::{SubscriberError, SubscriptionStatus},
+    templates::AdminSubscribersTemplate,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct SubscribersQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    status: Option<SubscriptionStatus>,
+    /// Case-insensitive substring matched against email and name.
+    q: Option<String>,
+    /// Opaque cursor from the previous page's `next_cursor`.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    after: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Treats an empty parameter, as sent by a form field left at its default,
+/// like a missing one.
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.is_empty() => {
+            T::deserialize(StringDeserializer::<D::Error>::new(value)).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriberRow {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<SubscriberRow>,
+    pub next_cursor: Option<String>,
+}
+
+/// Position in the `(subscribed_at, id)` ordering, handed to clients as an
+/// opaque URL-safe string.
+#[derive(Debug, PartialEq, Eq)]
+struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!(
+            "{}|{}",
+            self.subscribed_at.to_rfc3339(),
+            self.id
+        ))
+    }
+
+    fn decode(s: &str) -> Result<Cursor, SubscriberError> {
+        let invalid = || SubscriberError::ParseError("Invalid cursor".into());
+
+        let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (subscribed_at, id) = decoded.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Turns user input into an `ILIKE` pattern that matches it literally.
+fn search_pattern(q: &str) -> String {
+    let escaped = q
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+async fn fetch_subscriber_page(
+    pool: &Pool<Postgres>,
+    query: &SubscribersQuery,
+) -> Result<SubscriberPage, SubscriberError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let cursor = query.after.as_deref().map(Cursor::decode).transpose()?;
+    let pattern = query
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(search_pattern);
+
+    // Fetch one extra row to find out whether there is a next page.
+    let mut subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status as "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        WHERE ($1::subscription_status IS NULL OR status = $1)
+            AND ($2::TEXT IS NULL OR email ILIKE $2 OR name ILIKE $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR (subscribed_at, id) < ($3, $4::UUID))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $5
+        "#,
+        query.status as Option<SubscriptionStatus>,
+        pattern,
+        cursor.as_ref().map(|cursor| cursor.subscribed_at),
+        cursor.as_ref().map(|cursor| cursor.id),
+        limit + 1
+    )
+    .fetch_all(pool)
+    .instrument(tracing::info_span!("list subscribers query"))
+    .await
+    .map_err(SubscriberError::DatabaseError)?;
+
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|last| {
+            Cursor {
+                subscribed_at: last.subscribed_at,
+                id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(SubscriberPage {
+        subscribers,
+        next_cursor,
+    })
+}
+
+#[instrument(
+    name = "List subscribers",
+    skip(pool, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn list_subscribers(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<Pool<Postgres>>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = fetch_subscriber_page(pool.get_ref(), &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[instrument(
+    name = "Subscribers page",
+    skip(pool, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn subscribers_page(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<Pool<Postgres>>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = fetch_subscriber_page(pool.get_ref(), &query).await?;
+
+    let subscribers_template = AdminSubscribersTemplate {
+        subscribers: &page.subscribers,
+        next_cursor: page.next_cursor.as_deref(),
+        status: query.status.map(|status| status.as_str()),
+        q: query.q.as_deref().unwrap_or_default(),
+    };
+    let subscribers_rendered = subscribers_template.render().unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(subscribers_rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use claims::assert_err;
+    use uuid::Uuid;
+
+    use super::{search_pattern, Cursor};
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            subscribed_at: Utc::now(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(cursor, Cursor::decode(&cursor.encode()).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        assert_err!(Cursor::decode("not a cursor"));
+    }
+
+    #[test]
+    fn test_search_pattern_escapes_wildcards() {
+        assert_eq!(r"%50\%\_off%", search_pattern("50%_off"));
+    }
+}
@@ -0,0 +1,195 @@
+//! src/routes/admin/two_factor.rs
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    auth::{validate_credentials, AuthError, Credentials, UserId},
+    config::PasswordHashConfig,
+    routes::get_username,
+    session::{log_out_other_sessions, TypedSession},
+    templates::{AdminRecoveryCodesTemplate, AdminTwoFactorTemplate},
+    two_factor::{self, SecretCipher},
+};
+
+#[derive(Deserialize)]
+pub struct EnableTwoFactorFormData {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFactorFormData {
+    password: Secret<String>,
+}
+
+/// Shows whether the second factor is on, and if not, how to set up an
+/// authenticator app.
+///
+/// The secret offered stays the same until it is confirmed, so reloading
+/// the page does not invalidate an app that was already set up with it.
+#[tracing::instrument(
+    name = "Two-factor page",
+    skip(pool, session, user_id, flash_messages),
+    fields(user_id = %*user_id)
+)]
+pub async fn two_factor_page(
+    pool: web::Data<Pool<Postgres>>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages.iter().map(|m| m.content()).collect();
+    let enabled = two_factor::is_enabled(pool.get_ref(), **user_id)
+        .await
+        .map_err(AuthError::UnexpectedError)?;
+
+    let two_factor_rendered = if enabled {
+        AdminTwoFactorTemplate {
+            enabled,
+            secret: "",
+            otpauth_uri: "",
+            qr_code: "",
+            messages,
+        }
+        .render()
+        .unwrap()
+    } else {
+        let secret = match session
+            .get_pending_totp_secret()
+            .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+        {
+            Some(secret) => secret,
+            None => {
+                let secret = two_factor::generate_secret();
+                session
+                    .insert_pending_totp_secret(&secret)
+                    .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+                secret
+            }
+        };
+        let username = get_username(**user_id, pool.get_ref()).await?;
+        let otpauth_uri =
+            two_factor::otpauth_uri(&secret, &username).map_err(AuthError::UnexpectedError)?;
+        let qr_code =
+            two_factor::qr_code(&secret, &username).map_err(AuthError::UnexpectedError)?;
+
+        AdminTwoFactorTemplate {
+            enabled,
+            secret: &secret,
+            otpauth_uri: &otpauth_uri,
+            qr_code: &qr_code,
+            messages,
+        }
+        .render()
+        .unwrap()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(two_factor_rendered))
+}
+
+/// Turns on the second factor once the user enters a code from the app set
+/// up with the offered secret, and shows the recovery codes, once. Every
+/// other session of the user is logged out, since it was started without
+/// the second factor.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(form, pool, secret_cipher, session, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn enable_two_factor(
+    form: web::Form<EnableTwoFactorFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    secret_cipher: web::Data<SecretCipher>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(secret) = session
+        .get_pending_totp_secret()
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+    else {
+        return Ok(back_with_error(
+            "Scan the QR code again and enter a new code",
+        ));
+    };
+
+    let username = get_username(**user_id, pool.get_ref()).await?;
+    let recovery_codes = two_factor::enable(
+        pool.get_ref(),
+        &secret_cipher,
+        **user_id,
+        &username,
+        &secret,
+        &form.0.code,
+    )
+    .await
+    .map_err(AuthError::UnexpectedError)?;
+    let Some(recovery_codes) = recovery_codes else {
+        return Ok(back_with_error("The code is incorrect"));
+    };
+    tracing::info!("Two-factor authentication enabled");
+
+    log_out_other_sessions(pool.get_ref(), &session, **user_id)
+        .await
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+    session.remove_pending_totp_secret();
+
+    let recovery_codes_template = AdminRecoveryCodesTemplate {
+        recovery_codes: &recovery_codes,
+    };
+    let recovery_codes_rendered = recovery_codes_template.render().unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(recovery_codes_rendered))
+}
+
+/// Turns off the second factor, which takes the current password.
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(form, pool, password_hash_config, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn disable_two_factor(
+    form: web::Form<DisableTwoFactorFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    password_hash_config: web::Data<PasswordHashConfig>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, pool.get_ref()).await?;
+    let credentials = Credentials {
+        username,
+        password: form.0.password,
+    };
+    match validate_credentials(credentials, pool.get_ref(), &password_hash_config).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials) => {
+            return Ok(back_with_error("The password is incorrect"));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    two_factor::disable(pool.get_ref(), **user_id)
+        .await
+        .map_err(AuthError::UnexpectedError)?;
+    tracing::info!("Two-factor authentication disabled");
+
+    FlashMessage::info("Two-factor authentication is now off").send();
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/two_factor"))
+        .finish())
+}
+
+fn back_with_error(message: &str) -> HttpResponse {
+    FlashMessage::error(message).send();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/two_factor"))
+        .finish()
+}
@@ -8,7 +8,11 @@ use tracing::instrument;
 use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::domain::subscriber::SubscriberError;
+use crate::config::ConfirmationConfig;
+use crate::domain::subscriber::{SubscriberError, SubscriptionStatus};
+use crate::domain::HashedToken;
+use crate::subscriptions::transition_status;
+use crate::time::cutoff;
 
 #[derive(Debug, Deserialize)]
 pub struct ConfirmRequest {
@@ -16,7 +20,7 @@ pub struct ConfirmRequest {
 }
 
 #[instrument(
-    skip(pool),
+    skip(info, pool, confirmation_config),
     fields(
         request_id = %Uuid::new_v4(),
     )
@@ -24,49 +28,55 @@ pub struct ConfirmRequest {
 pub async fn confirm(
     info: web::Query<ConfirmRequest>,
     pool: web::Data<Pool<Postgres>>,
+    confirmation_config: web::Data<ConfirmationConfig>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    info!("Confirming subscription {}", info.token);
+    info!("Confirming subscription");
+
+    let token = HashedToken::parse(info.0.token).ok_or(SubscriberError::InvalidToken)?;
+    let token_hash = token.hash();
+
+    let mut transaction = pool.begin().await.map_err(SubscriberError::DatabaseError)?;
 
     let subscription_token = sqlx::query!(
         r#"
-        SELECT subscription_token, subscriber_id FROM subscription_tokens
-        WHERE subscription_token = $1        
+        SELECT subscription_token_hash, subscriber_id FROM subscription_tokens
+        WHERE subscription_token_hash = $1 AND created_at > $2
+        FOR UPDATE
         "#,
-        info.token,
+        token_hash,
+        cutoff(confirmation_config.token_ttl)
     )
-    .fetch_one(pool.get_ref())
+    .fetch_optional(&mut *transaction)
     .instrument(tracing::info_span!("confirm subscription query"))
     .await
-    .map_err(|_e| SubscriberError::InvalidToken(info.token.clone()))?;
+    .map_err(SubscriberError::DatabaseError)?
+    .filter(|record| token.matches(&record.subscription_token_hash))
+    .ok_or(SubscriberError::InvalidToken)?;
 
-    info!(
-        "Subscription confirmed {}",
-        subscription_token.subscription_token
-    );
-    sqlx::query!(
-        r#"
-        UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
-        "#,
-        subscription_token.subscriber_id
+    transition_status(
+        &mut transaction,
+        subscription_token.subscriber_id,
+        SubscriptionStatus::Confirmed,
     )
-    .execute(pool.get_ref())
-    .instrument(tracing::info_span!("set subscription to confirmed"))
-    .await
-    .map_err(SubscriberError::DatabaseError)?;
+    .await?;
 
-    info!("Updated subscription to confirmed");
     sqlx::query!(
         r#"
         DELETE FROM subscription_tokens
-        WHERE subscription_token = $1
+        WHERE subscription_token_hash = $1
         "#,
-        info.token
+        token_hash
     )
-    .execute(pool.get_ref())
+    .execute(&mut *transaction)
     .instrument(tracing::info_span!("delete subscription token"))
     .await
     .map_err(SubscriberError::DatabaseError)?;
 
-    info!("Deleted subscription token {}", info.token);
+    transaction
+        .commit()
+        .await
+        .map_err(SubscriberError::DatabaseError)?;
+
+    info!("Subscription confirmed");
     Ok(HttpResponse::Ok().finish())
 }
@@ -1,6 +1,29 @@
 //! src/routes/health_check.rs
-use actix_web::HttpResponse;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
+use actix_web::{web, HttpResponse};
+
+/// Whether the application is ready to take traffic, i.e. its database
+/// schema is up to date and the background workers are running.
+#[derive(Debug, Default)]
+pub struct Readiness(AtomicBool);
+
+impl Readiness {
+    pub fn mark_ready(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Answers 200 once the application is ready, and 503 while startup
+/// migrations are still pending.
+pub async fn health_check(readiness: web::Data<Readiness>) -> HttpResponse {
+    if readiness.is_ready() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::ServiceUnavailable().finish()
+    }
 }
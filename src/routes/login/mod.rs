@@ -1,5 +1,7 @@
 mod get;
 mod post;
+mod two_factor;
 
 pub use get::*;
 pub use post::*;
+pub use two_factor::*;
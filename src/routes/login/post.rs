@@ -1,8 +1,13 @@
 use actix_web::{http::header, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use sqlx::{Pool, Postgres};
 
-use crate::auth::{validate_credentials, Credentials};
+use crate::auth::{validate_credentials, AuthError, Credentials};
+use crate::config::PasswordHashConfig;
+use crate::session::{PendingLogin, TypedSession};
+use crate::two_factor;
 
 #[derive(serde::Deserialize)]
 pub struct LoginFormData {
@@ -11,23 +16,62 @@ pub struct LoginFormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool),
+    skip(form, pool, password_hash_config, session),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
     )]
 pub async fn login(
     form: web::Form<LoginFormData>,
     pool: web::Data<Pool<Postgres>>,
+    password_hash_config: web::Data<PasswordHashConfig>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
-    let user_id = validate_credentials(credentials, pool.get_ref()).await?;
-    tracing::Span::current().record("user_id", &tracing::field::display(user_id));
+    let user_id =
+        match validate_credentials(credentials, pool.get_ref(), password_hash_config.get_ref())
+            .await
+        {
+            Ok(user_id) => user_id,
+            Err(AuthError::InvalidCredentials) => {
+                FlashMessage::error("Authentication failed").send();
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((header::LOCATION, "/login"))
+                    .finish());
+            }
+            Err(e) => return Err(e.into()),
+        };
+    tracing::Span::current().record("user_id", tracing::field::display(user_id));
+
+    session.renew();
+    let second_factor_enabled = two_factor::is_enabled(pool.get_ref(), user_id)
+        .await
+        .map_err(AuthError::UnexpectedError)?;
+    if second_factor_enabled {
+        // Whoever was logged in before stays logged out until the second
+        // factor is entered.
+        session.remove_user_id();
+        let pending_login = PendingLogin {
+            user_id,
+            started_at: Utc::now(),
+        };
+        session
+            .insert_pending_login(&pending_login)
+            .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, "/login/two_factor"))
+            .finish());
+    }
+
+    session.remove_pending_login();
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
 
     Ok(HttpResponse::SeeOther()
-        .insert_header((header::LOCATION, "/"))
+        .insert_header((header::LOCATION, "/admin/dashboard"))
         .finish())
 }
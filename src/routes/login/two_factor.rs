@@ -0,0 +1,118 @@
+//! src/routes/login/two_factor.rs
+
+use std::time::Duration;
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    auth::AuthError,
+    session::{PendingLogin, TypedSession},
+    templates::LoginTwoFactorTemplate,
+    time::cutoff,
+    two_factor::{self, SecretCipher, Verification},
+};
+
+/// How long after entering the password the second factor is accepted.
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Deserialize)]
+pub struct LoginTwoFactorFormData {
+    code: String,
+}
+
+/// The login in progress, unless it expired or there is none.
+fn pending_login(session: &TypedSession) -> Result<Option<PendingLogin>, AuthError> {
+    let pending_login = session
+        .get_pending_login()
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+    Ok(pending_login.filter(|login| login.started_at > cutoff(PENDING_LOGIN_TTL)))
+}
+
+fn back_to_login(message: &str) -> HttpResponse {
+    FlashMessage::error(message).send();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish()
+}
+
+#[tracing::instrument(skip(session, flash_messages))]
+pub async fn login_two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if pending_login(&session)?.is_none() {
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login"))
+            .finish());
+    }
+
+    let two_factor_template = LoginTwoFactorTemplate {
+        messages: flash_messages.iter().map(|m| m.content()).collect(),
+    };
+    let two_factor_rendered = two_factor_template.render().unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(two_factor_rendered))
+}
+
+/// Completes a login with a code from the user's authenticator app or one
+/// of their recovery codes.
+#[tracing::instrument(
+    skip(form, pool, secret_cipher, session),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    form: web::Form<LoginTwoFactorFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    secret_cipher: web::Data<SecretCipher>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(pending_login) = pending_login(&session)? else {
+        session.remove_pending_login();
+        return Ok(back_to_login("Your login expired, please log in again"));
+    };
+    let user_id = pending_login.user_id;
+    tracing::Span::current().record("user_id", tracing::field::display(user_id));
+
+    let verification = two_factor::verify(pool.get_ref(), &secret_cipher, user_id, &form.0.code)
+        .await
+        .map_err(AuthError::UnexpectedError)?;
+    match verification {
+        Verification::Valid => {}
+        Verification::Invalid => {
+            FlashMessage::error("The code is incorrect").send();
+            return Ok(HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/login/two_factor"))
+                .finish());
+        }
+        Verification::LockedOut => {
+            tracing::warn!("Second factor locked after too many wrong codes");
+            session.remove_pending_login();
+            return Ok(back_to_login(
+                "Too many wrong codes, please try again in a few minutes",
+            ));
+        }
+        Verification::Disabled => {
+            tracing::warn!("User was disabled during the second step of the login");
+            session.remove_pending_login();
+            return Ok(back_to_login("Authentication failed"));
+        }
+    }
+
+    session.renew();
+    session.remove_pending_login();
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/dashboard"))
+        .finish())
+}
@@ -1,13 +1,19 @@
+mod admin;
 mod confirm;
 mod health_check;
 mod home;
 mod login;
 mod newsletter;
+mod password_reset;
 mod subscriptions;
+mod unsubscribe;
 
+pub use admin::*;
 pub use confirm::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use newsletter::*;
+pub use password_reset::*;
 pub use subscriptions::*;
+pub use unsubscribe::*;
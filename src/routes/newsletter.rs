@@ -2,21 +2,20 @@
 
 use crate::{
     auth::validate_request,
-    domain::{
-        newsletter::{Newsletter, NewsletterError},
-        subscriber::{Subscriber, SubscriberError},
-    },
-    email::{Email, EmailService},
+    config::PasswordHashConfig,
+    domain::newsletter::{Newsletter, NewsletterError},
+    domain::subscriber::SubscriptionStatus,
+    idempotency::{save_response, try_processing, IdempotencyError, IdempotencyKey, NextAction},
 };
-use actix_web::{web, HttpResponse};
-use sqlx::{Pool, Postgres};
-use std::sync::Arc;
+use actix_web::{http::header::HeaderMap, web, HttpResponse};
+use chrono::Utc;
+use sqlx::{Pool, Postgres, Transaction};
 use tracing::{info, instrument, Instrument};
 use uuid::Uuid;
 
 #[instrument(
     name = "Publish a newsletter issue",
-    skip(json, pool, email_service, request),
+    skip(json, pool, password_hash_config, request),
     fields(
         request_id = %Uuid::new_v4(),
         username=tracing::field::Empty,
@@ -26,42 +25,122 @@ use uuid::Uuid;
 pub async fn publish_newsletter(
     json: web::Json<Newsletter>,
     pool: web::Data<Pool<Postgres>>,
-    email_service: web::Data<Arc<dyn EmailService + Send + Sync>>,
+    password_hash_config: web::Data<PasswordHashConfig>,
     request: actix_web::HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = validate_request(request, pool.get_ref()).await?;
+    let headers = request.headers().clone();
+    let user_id = validate_request(request, pool.get_ref(), password_hash_config.get_ref()).await?;
 
-    tracing::Span::current().record("user_id", &tracing::field::display(user_id));
+    tracing::Span::current().record("user_id", tracing::field::display(user_id));
 
-    let confirmed_emails: Vec<Subscriber> = sqlx::query_as!(
-        Subscriber,
-        r#"
-        SELECT email, name, status
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#
+    let idempotency_key = idempotency_key(&headers)?;
+
+    publish_issue(
+        pool.get_ref(),
+        user_id,
+        &json,
+        idempotency_key,
+        HttpResponse::Accepted().finish(),
     )
-    .fetch_all(pool.get_ref())
-    .instrument(tracing::info_span!("get confirmed emails query"))
     .await
-    .map_err(NewsletterError::DatabaseError)?;
+}
+
+/// Stores `newsletter` and queues one delivery per confirmed subscriber,
+/// answering with `response`. When `idempotency_key` has been seen before
+/// for `user_id`, the saved response is replayed and nothing is queued.
+pub(crate) async fn publish_issue(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    newsletter: &Newsletter,
+    idempotency_key: Option<IdempotencyKey>,
+    response: HttpResponse,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = match idempotency_key {
+        Some(ref key) => match try_processing(pool, key, user_id).await? {
+            NextAction::StartProcessing(transaction) => *transaction,
+            NextAction::ReturnSavedResponse(saved_response) => {
+                info!("Replaying saved response for idempotency key");
+                return Ok(saved_response);
+            }
+        },
+        None => pool.begin().await.map_err(NewsletterError::DatabaseError)?,
+    };
 
-    info!("Confirmed email addresses: {}", confirmed_emails.len());
+    let issue_id = insert_newsletter_issue(&mut transaction, newsletter).await?;
+    let queued = enqueue_delivery_tasks(&mut transaction, issue_id).await?;
 
-    for confirmed_email in confirmed_emails {
-        let email = Email {
-            to: &confirmed_email.email.to_string(),
-            html: &json.html,
-            from: "",
-            subject: &json.subject,
-            reply_to: "",
-            plaintext: &json.text,
-        };
+    info!(
+        "Queued newsletter issue {} for {} subscribers",
+        issue_id, queued
+    );
 
-        email_service
-            .send(email)
-            .map_err(SubscriberError::EmailError)?
+    match idempotency_key {
+        Some(ref key) => Ok(save_response(transaction, key, user_id, response).await?),
+        None => {
+            transaction
+                .commit()
+                .await
+                .map_err(NewsletterError::DatabaseError)?;
+            Ok(response)
+        }
     }
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, IdempotencyError> {
+    headers
+        .get("Idempotency-Key")
+        .map(|value| {
+            let value = value
+                .to_str()
+                .map_err(|e| IdempotencyError::InvalidKey(e.to_string()))?;
+            IdempotencyKey::parse(value.to_string())
+        })
+        .transpose()
+}
+
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter: &Newsletter,
+) -> Result<Uuid, NewsletterError> {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, subject, text_content, html_content, published_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        issue_id,
+        newsletter.subject,
+        newsletter.text,
+        newsletter.html,
+        Utc::now()
+    )
+    .execute(&mut **transaction)
+    .instrument(tracing::info_span!("insert newsletter issue query"))
+    .await
+    .map_err(NewsletterError::DatabaseError)?;
+
+    Ok(issue_id)
+}
+
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<u64, NewsletterError> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = $2
+        "#,
+        issue_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus
+    )
+    .execute(&mut **transaction)
+    .instrument(tracing::info_span!("enqueue delivery tasks query"))
+    .await
+    .map_err(NewsletterError::DatabaseError)?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(result.rows_affected())
 }
@@ -0,0 +1,335 @@
+//! src/routes/password_reset.rs
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tracing::{info, instrument, Instrument};
+use uuid::Uuid;
+
+use crate::{
+    app::ApplicationBaseUrl,
+    config::{PasswordHashConfig, PasswordPolicyConfig, PasswordResetConfig},
+    domain::{
+        password::{NewPassword, PasswordError},
+        HashedToken,
+    },
+    email::{Email, EmailError, EmailService},
+    outbox::OutboxEmailService,
+    session::delete_user_sessions,
+    templates::{
+        ForgotPasswordTemplate, PasswordResetEmailHtmlTemplate, PasswordResetEmailSubject,
+        PasswordResetEmailTxtTemplate, ResetPasswordTemplate,
+    },
+    time::cutoff,
+    users::hash_password,
+};
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordFormData {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordQuery {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[instrument(skip(flash_messages))]
+pub async fn forgot_password_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let forgot_template = ForgotPasswordTemplate {
+        messages: flash_messages.iter().map(|m| m.content()).collect(),
+    };
+    let forgot_rendered = forgot_template.render().unwrap();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(forgot_rendered)
+}
+
+/// Emails a single-use reset link to the active user with this address.
+///
+/// The response is the same whether or not such a user exists, so the form
+/// cannot be used to find out which addresses have an account. A user is
+/// sent at most one link per `resend_interval`; a new link replaces the
+/// previous one.
+#[instrument(
+    skip(form, pool, base_url, password_reset_config),
+    fields(request_id = %Uuid::new_v4(), user_id = tracing::field::Empty)
+)]
+pub async fn forgot_password(
+    form: web::Form<ForgotPasswordFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    password_reset_config: web::Data<PasswordResetConfig>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.0.email.trim().to_string();
+    let response = HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/password/forgot"))
+        .finish();
+    FlashMessage::info(
+        "If this address belongs to an account, a link to reset the password is on its way",
+    )
+    .send();
+
+    let mut transaction = pool.begin().await.map_err(PasswordError::DatabaseError)?;
+
+    let user = sqlx::query!(
+        r#"
+        SELECT id, email as "email!"
+        FROM users
+        WHERE lower(email) = lower($1) AND disabled_at IS NULL
+        FOR UPDATE
+        "#,
+        email
+    )
+    .fetch_optional(&mut *transaction)
+    .instrument(tracing::info_span!("get user by email query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?;
+    let Some(user) = user else {
+        info!("No active user with this email address");
+        return Ok(response);
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(user.id));
+
+    let last_sent_at = sqlx::query!(
+        "SELECT max(created_at) as last_sent_at FROM password_reset_tokens WHERE user_id = $1",
+        user.id
+    )
+    .fetch_one(&mut *transaction)
+    .instrument(tracing::info_span!("get last reset email query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?
+    .last_sent_at;
+    if last_sent_at.is_some_and(|sent_at| sent_at > cutoff(password_reset_config.resend_interval)) {
+        info!("A reset email was sent recently, not sending another one");
+        return Ok(response);
+    }
+
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user.id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("delete previous reset tokens query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?;
+
+    let token = HashedToken::generate();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at)
+        VALUES ($1, $2, $3)
+        "#,
+        token.hash(),
+        user.id,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("add reset token query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?;
+
+    send_reset_email(
+        &user.email,
+        &base_url.0,
+        token.as_ref(),
+        &OutboxEmailService::new(&mut transaction),
+    )
+    .await
+    .map_err(PasswordError::EmailError)?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(PasswordError::DatabaseError)?;
+
+    info!("Password reset email queued");
+    Ok(response)
+}
+
+async fn send_reset_email(
+    to: &str,
+    base_url: &str,
+    token: &str,
+    email_service: &(dyn EmailService + Sync),
+) -> Result<(), EmailError> {
+    let html = PasswordResetEmailHtmlTemplate { base_url, token };
+    let plaintext = PasswordResetEmailTxtTemplate { base_url, token };
+    let subject = PasswordResetEmailSubject {};
+
+    let email = Email {
+        to,
+        from: "",
+        subject: &subject.render().unwrap(),
+        reply_to: "",
+        plaintext: &plaintext.render().unwrap(),
+        html: &html.render().unwrap(),
+        headers: &[],
+    };
+    email_service.send(email).await
+}
+
+/// Landing page for the emailed link, asking for the new password.
+#[instrument(
+    skip(query, pool, password_reset_config, flash_messages),
+    fields(request_id = %Uuid::new_v4())
+)]
+pub async fn reset_password_form(
+    query: web::Query<ResetPasswordQuery>,
+    pool: web::Data<Pool<Postgres>>,
+    password_reset_config: web::Data<PasswordResetConfig>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(token) = HashedToken::parse(query.0.token) else {
+        return Ok(invalid_link(PasswordError::InvalidToken));
+    };
+
+    let valid = sqlx::query!(
+        "SELECT user_id FROM password_reset_tokens WHERE token_hash = $1 AND created_at > $2",
+        token.hash(),
+        cutoff(password_reset_config.token_ttl)
+    )
+    .fetch_optional(pool.get_ref())
+    .instrument(tracing::info_span!("get reset token query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?
+    .is_some();
+    if !valid {
+        return Ok(invalid_link(PasswordError::InvalidToken));
+    }
+
+    let reset_template = ResetPasswordTemplate {
+        token: token.as_ref(),
+        messages: flash_messages.iter().map(|m| m.content()).collect(),
+    };
+    let reset_rendered = reset_template.render().unwrap();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(reset_rendered))
+}
+
+/// Sets a new password with a reset token, which is used up, and logs the
+/// user out everywhere.
+#[instrument(
+    skip(form, pool, password_hash_config, password_policy, password_reset_config),
+    fields(request_id = %Uuid::new_v4(), user_id = tracing::field::Empty)
+)]
+pub async fn reset_password(
+    form: web::Form<ResetPasswordFormData>,
+    pool: web::Data<Pool<Postgres>>,
+    password_hash_config: web::Data<PasswordHashConfig>,
+    password_policy: web::Data<PasswordPolicyConfig>,
+    password_reset_config: web::Data<PasswordResetConfig>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetPasswordFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.0;
+    let Some(token) = HashedToken::parse(token) else {
+        return Ok(invalid_link(PasswordError::InvalidToken));
+    };
+
+    let back_with_error = |message: &str| {
+        FlashMessage::error(message).send();
+        HttpResponse::SeeOther()
+            .insert_header((
+                LOCATION,
+                format!("/password/reset?token={}", token.as_ref()),
+            ))
+            .finish()
+    };
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Ok(back_with_error(
+            "You entered two different new passwords - the field values must match",
+        ));
+    }
+    let new_password = match NewPassword::parse(new_password, &password_policy) {
+        Ok(new_password) => new_password,
+        Err(e) => return Ok(back_with_error(&e.to_string())),
+    };
+    let mut transaction = pool.begin().await.map_err(PasswordError::DatabaseError)?;
+
+    // Check the token before the costly hashing, so that made-up tokens
+    // cannot keep the server busy. The lock makes the token single-use: a
+    // concurrent reset with the same token waits here and then finds it
+    // deleted.
+    let user_id = sqlx::query!(
+        r#"
+        SELECT user_id FROM password_reset_tokens
+        WHERE token_hash = $1 AND created_at > $2
+        FOR UPDATE
+        "#,
+        token.hash(),
+        cutoff(password_reset_config.token_ttl)
+    )
+    .fetch_optional(&mut *transaction)
+    .instrument(tracing::info_span!("lock reset token query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?
+    .map(|record| record.user_id);
+    let Some(user_id) = user_id else {
+        return Ok(invalid_link(PasswordError::InvalidToken));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(user_id));
+
+    let password_hash = hash_password(new_password.as_ref(), &password_hash_config)
+        .await
+        .map_err(PasswordError::UnexpectedError)?;
+
+    sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE token_hash = $1",
+        token.hash()
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("use reset token query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("update password query"))
+    .await
+    .map_err(PasswordError::DatabaseError)?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(PasswordError::DatabaseError)?;
+
+    delete_user_sessions(pool.get_ref(), user_id)
+        .await
+        .map_err(PasswordError::DatabaseError)?;
+
+    info!("Password reset");
+    FlashMessage::info("Your password has been reset, you can now log in").send();
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish())
+}
+
+/// Sends the user back to ask for a new link.
+fn invalid_link(error: PasswordError) -> HttpResponse {
+    FlashMessage::error(error.to_string()).send();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/password/forgot"))
+        .finish()
+}
@@ -1,16 +1,26 @@
 use crate::{
-    domain::subscriber::{Subscriber, SubscriberEmail, SubscriberError, SubscriberName},
-    email::{Email, EmailService},
+    app::ApplicationBaseUrl,
+    config::ConfirmationConfig,
+    domain::{
+        subscriber::{
+            Subscriber, SubscriberEmail, SubscriberError, SubscriberName, SubscriptionStatus,
+        },
+        HashedToken,
+    },
+    email::{Email, EmailError, EmailService},
+    outbox::OutboxEmailService,
+    subscriptions::{record_status_change, transition_status},
     templates::{
         ConfirmationEmailHtmlTemplate, ConfirmationEmailSubject, ConfirmationEmailTxtTemplate,
     },
+    time::cutoff,
 };
 use actix_web::{web, HttpResponse};
 use askama::Template;
 use chrono::Utc;
 use serde::Deserialize;
-use sqlx::{Pool, Postgres};
-use std::{fmt::Debug, sync::Arc};
+use sqlx::{Pool, Postgres, Transaction};
+use std::fmt::Debug;
 use tracing::{info, instrument, Instrument};
 use uuid::Uuid;
 
@@ -27,13 +37,22 @@ fn parse_subscriber(data: SubscriberFormData) -> Result<Subscriber, SubscriberEr
     let new_subscriber = Subscriber {
         email,
         name,
-        status: "pending".to_string(),
+        status: SubscriptionStatus::Pending,
     };
     Ok(new_subscriber)
 }
 
+/// Signs up a new subscriber and queues an email with a confirmation link.
+///
+/// The subscriber, their token and the email are committed together, so a
+/// failure at any point leaves nothing behind.
+///
+/// Signing up again with an address that has not been confirmed, or that
+/// unsubscribed, replaces the outstanding confirmation link with a fresh
+/// one, at most once per `resend_interval`. Already confirmed addresses are
+/// left untouched.
 #[instrument(
-    skip(data, pool, email_service),
+    skip(data, pool, base_url, confirmation_config),
     fields(
         request_id = %Uuid::new_v4(),
         subscriber_email = %data.email,
@@ -43,63 +62,151 @@ fn parse_subscriber(data: SubscriberFormData) -> Result<Subscriber, SubscriberEr
 pub async fn subscribe(
     data: web::Form<SubscriberFormData>,
     pool: web::Data<Pool<Postgres>>,
-    email_service: web::Data<Arc<dyn EmailService + Send + Sync>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    confirmation_config: web::Data<ConfirmationConfig>,
 ) -> Result<HttpResponse, actix_web::Error> {
     info!("Adding a new subscriber");
 
     let new_subscriber = parse_subscriber(data.0)?;
 
-    let subscription_record = sqlx::query!(
-        r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending')
-        RETURNING id, email, name, subscribed_at, status
-        "#,
-        Uuid::new_v4(),
-        new_subscriber.email.as_ref(),
-        new_subscriber.name.as_ref(),
-        Utc::now()
-    )
-    .fetch_one(pool.get_ref())
-    .instrument(tracing::info_span!("add subscriber query"))
-    .await
-    .map_err(SubscriberError::DatabaseError)?;
+    let mut transaction = pool.begin().await.map_err(SubscriberError::DatabaseError)?;
+
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await? {
+        Some(subscriber_id) => {
+            info!("New subscriber details has been saved");
+            subscriber_id
+        }
+        None => {
+            let existing = sqlx::query!(
+                r#"
+                SELECT id, status as "status: SubscriptionStatus"
+                FROM subscriptions
+                WHERE email = $1
+                FOR UPDATE
+                "#,
+                new_subscriber.email.as_ref()
+            )
+            .fetch_one(&mut *transaction)
+            .instrument(tracing::info_span!("get existing subscriber query"))
+            .await
+            .map_err(SubscriberError::DatabaseError)?;
+
+            if existing.status == SubscriptionStatus::Confirmed {
+                info!("Subscriber is already confirmed");
+                return Ok(HttpResponse::Ok().finish());
+            }
+
+            let last_sent_at = sqlx::query!(
+                "SELECT max(created_at) as last_sent_at FROM subscription_tokens WHERE subscriber_id = $1",
+                existing.id
+            )
+            .fetch_one(&mut *transaction)
+            .instrument(tracing::info_span!("get last confirmation email query"))
+            .await
+            .map_err(SubscriberError::DatabaseError)?
+            .last_sent_at;
+
+            if last_sent_at
+                .is_some_and(|sent_at| sent_at > cutoff(confirmation_config.resend_interval))
+            {
+                return Err(SubscriberError::RateLimited(
+                    "A confirmation email was sent recently, check your inbox".into(),
+                )
+                .into());
+            }
+
+            transition_status(&mut transaction, existing.id, SubscriptionStatus::Pending).await?;
+
+            sqlx::query!(
+                "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+                existing.id
+            )
+            .execute(&mut *transaction)
+            .instrument(tracing::info_span!(
+                "delete previous subscription tokens query"
+            ))
+            .await
+            .map_err(SubscriberError::DatabaseError)?;
 
-    info!("New subscriber details has been saved");
+            info!("Resending confirmation to existing subscriber");
+            existing.id
+        }
+    };
 
-    let subscription_token = Uuid::new_v4().to_string();
+    let subscription_token = HashedToken::generate();
 
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at)
+        VALUES ($1, $2, $3)
         "#,
-        subscription_token,
-        subscription_record.id
+        subscription_token.hash(),
+        subscriber_id,
+        Utc::now()
     )
-    .execute(pool.get_ref())
+    .execute(&mut *transaction)
     .instrument(tracing::info_span!("add subscription token query"))
     .await
     .map_err(SubscriberError::DatabaseError)?;
 
     send_confirmation_email(
-        &subscription_record.email,
-        &subscription_token,
-        email_service,
+        new_subscriber.email.as_ref(),
+        &base_url.0,
+        subscription_token.as_ref(),
+        &OutboxEmailService::new(&mut transaction),
     )
+    .await
     .map_err(SubscriberError::EmailError)?;
 
-    info!("Email sent");
+    transaction
+        .commit()
+        .await
+        .map_err(SubscriberError::DatabaseError)?;
+
+    info!("Confirmation email queued");
     Ok(HttpResponse::Ok().finish())
 }
 
-fn send_confirmation_email(
+/// Inserts `new_subscriber`, returning its id, or `None` if the email address
+/// is already taken.
+async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &Subscriber,
+) -> Result<Option<Uuid>, SubscriberError> {
+    let subscriber_id = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        Utc::now(),
+        new_subscriber.status as SubscriptionStatus
+    )
+    .fetch_optional(&mut **transaction)
+    .instrument(tracing::info_span!("add subscriber query"))
+    .await
+    .map_err(SubscriberError::DatabaseError)?
+    .map(|record| record.id);
+
+    if let Some(subscriber_id) = subscriber_id {
+        record_status_change(transaction, subscriber_id, None, new_subscriber.status).await?;
+    }
+
+    Ok(subscriber_id)
+}
+
+async fn send_confirmation_email(
     new_subscriber_email: &str,
+    base_url: &str,
     token: &str,
-    email_service: web::Data<Arc<dyn EmailService + Send + Sync>>,
-) -> Result<(), String> {
-    let confirm_email_html = ConfirmationEmailHtmlTemplate { token };
-    let confirm_email_plaintext = ConfirmationEmailTxtTemplate { token };
+    email_service: &(dyn EmailService + Sync),
+) -> Result<(), EmailError> {
+    let confirm_email_html = ConfirmationEmailHtmlTemplate { base_url, token };
+    let confirm_email_plaintext = ConfirmationEmailTxtTemplate { base_url, token };
     let confirm_subject = ConfirmationEmailSubject {};
 
     let email = Email {
@@ -109,6 +216,7 @@ fn send_confirmation_email(
         reply_to: "",
         plaintext: &confirm_email_plaintext.render().unwrap(),
         html: &confirm_email_html.render().unwrap(),
+        headers: &[],
     };
-    email_service.send(email)
+    email_service.send(email).await
 }
@@ -0,0 +1,80 @@
+//! src/routes/unsubscribe.rs
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::domain::subscriber::{SubscriberError, SubscriptionStatus};
+use crate::subscriptions::transition_status;
+use crate::templates::{UnsubscribeConfirmTemplate, UnsubscribeDoneTemplate};
+use crate::unsubscribe::UnsubscribeLinks;
+
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeRequest {
+    token: String,
+}
+
+/// Landing page for the link in the newsletter footer.
+///
+/// Opening the link must not unsubscribe anybody on its own, since mail
+/// scanners follow links; the page asks the reader to confirm instead.
+#[instrument(
+    skip(info, unsubscribe_links),
+    fields(request_id = %Uuid::new_v4())
+)]
+pub async fn unsubscribe_form(
+    info: web::Query<UnsubscribeRequest>,
+    unsubscribe_links: web::Data<UnsubscribeLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    unsubscribe_links.verify(&info.token)?;
+
+    let confirm_template = UnsubscribeConfirmTemplate { token: &info.token };
+    let confirm_rendered = confirm_template.render().unwrap();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(confirm_rendered))
+}
+
+/// Unsubscribes the subscriber the token was issued for.
+///
+/// Serves both the confirmation form and RFC 8058 one-click requests sent by
+/// mail clients with a `List-Unsubscribe=One-Click` body, which carries no
+/// information beyond the token and is therefore not inspected. Repeating the
+/// request is harmless.
+#[instrument(
+    skip(info, pool, unsubscribe_links),
+    fields(request_id = %Uuid::new_v4(), subscriber_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe(
+    info: web::Query<UnsubscribeRequest>,
+    pool: web::Data<Pool<Postgres>>,
+    unsubscribe_links: web::Data<UnsubscribeLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = unsubscribe_links.verify(&info.token)?;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber_id));
+
+    let mut transaction = pool.begin().await.map_err(SubscriberError::DatabaseError)?;
+    transition_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .map_err(SubscriberError::DatabaseError)?;
+
+    info!("Subscriber unsubscribed");
+
+    let done_template = UnsubscribeDoneTemplate {};
+    let done_rendered = done_template.render().unwrap();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(done_rendered))
+}
@@ -0,0 +1,269 @@
+//! src/session.rs
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::cookie::time::Duration;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres};
+use tracing::Instrument;
+use uuid::Uuid;
+
+type SessionState = HashMap<String, String>;
+
+/// Server-side session storage backed by the `sessions` table.
+///
+/// The cookie only carries the signed session key; the state itself never
+/// leaves the database.
+#[derive(Clone)]
+pub struct PgSessionStore {
+    pool: Pool<Postgres>,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+fn generate_session_key() -> SessionKey {
+    let value: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect();
+
+    value
+        .try_into()
+        .expect("A 64 character session key is always valid")
+}
+
+fn expires_at(ttl: &Duration) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT state as "state: Json<SessionState>"
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .instrument(tracing::info_span!("load session query"))
+        .await
+        .map_err(|e| LoadError::Other(e.into()))?;
+
+        Ok(record.map(|record| record.state.0))
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            session_key.as_ref(),
+            Json(session_state) as _,
+            expires_at(ttl)
+        )
+        .execute(&self.pool)
+        .instrument(tracing::info_span!("save session query"))
+        .await
+        .map_err(|e| SaveError::Other(e.into()))?;
+
+        sqlx::query!("DELETE FROM sessions WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .instrument(tracing::info_span!("delete expired sessions query"))
+            .await
+            .map_err(|e| SaveError::Other(e.into()))?;
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET state = $2, expires_at = $3
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            Json(&session_state) as _,
+            expires_at(ttl)
+        )
+        .execute(&self.pool)
+        .instrument(tracing::info_span!("update session query"))
+        .await
+        .map_err(|e| UpdateError::Other(e.into()))?
+        .rows_affected();
+
+        if updated > 0 {
+            return Ok(session_key);
+        }
+
+        // The session expired or was purged in the meantime, start a new one.
+        self.save(session_state, ttl).await.map_err(|e| match e {
+            SaveError::Serialization(e) => UpdateError::Serialization(e),
+            SaveError::Other(e) => UpdateError::Other(e),
+        })
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        sqlx::query!(
+            "UPDATE sessions SET expires_at = $2 WHERE session_key = $1",
+            session_key.as_ref(),
+            expires_at(ttl)
+        )
+        .execute(&self.pool)
+        .instrument(tracing::info_span!("update session ttl query"))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM sessions WHERE session_key = $1",
+            session_key.as_ref()
+        )
+        .execute(&self.pool)
+        .instrument(tracing::info_span!("delete session query"))
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Deletes every session logged in as `user_id` or waiting for their second
+/// factor, logging the user out everywhere. Returns the number of sessions
+/// deleted.
+pub async fn delete_user_sessions(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    // Session values are stored JSON-encoded, hence the quotes and the cast.
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM sessions
+        WHERE state->>'user_id' = $1
+            OR (state->>'pending_login')::jsonb->>'user_id' = $2
+        "#,
+        format!("\"{}\"", user_id),
+        user_id.to_string()
+    )
+    .execute(pool)
+    .instrument(tracing::info_span!("delete user sessions query"))
+    .await?
+    .rows_affected();
+
+    Ok(deleted)
+}
+
+/// Logs `user_id` out of every session but `session`, which is kept alive
+/// under a new key. Returns the number of sessions deleted.
+pub async fn log_out_other_sessions(
+    pool: &Pool<Postgres>,
+    session: &TypedSession,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let deleted = delete_user_sessions(pool, user_id).await?;
+    session.renew();
+
+    Ok(deleted)
+}
+
+/// A user who entered the right password but still has to enter their
+/// second factor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+}
+
+/// A [`Session`] with typed accessors for the keys this application uses.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const PENDING_LOGIN_KEY: &'static str = "pending_login";
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+
+    /// Rotates the session key, e.g. on login, to prevent session fixation.
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    pub fn remove_user_id(&self) {
+        self.0.remove(Self::USER_ID_KEY);
+    }
+
+    pub fn insert_pending_login(&self, login: &PendingLogin) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_LOGIN_KEY, login)
+    }
+
+    pub fn get_pending_login(&self) -> Result<Option<PendingLogin>, SessionGetError> {
+        self.0.get(Self::PENDING_LOGIN_KEY)
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    /// The secret shown while setting up an authenticator app, kept until
+    /// the user proves the app works.
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
@@ -0,0 +1,55 @@
+//! src/subscription_tokens.rs
+
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, Instrument};
+
+use crate::config::ConfirmationConfig;
+use crate::time::cutoff;
+
+/// How often expired confirmation tokens are purged.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Spawns a task that deletes expired confirmation tokens every
+/// [`CLEANUP_INTERVAL`] until `shutdown` is cancelled.
+pub fn spawn_cleanup(
+    config: &ConfirmationConfig,
+    pool: Pool<Postgres>,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    let token_ttl = config.token_ttl;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => break,
+            }
+            match delete_expired_tokens(&pool, token_ttl).await {
+                Ok(deleted) => info!("Deleted {} expired confirmation tokens", deleted),
+                Err(e) => error!("Error deleting expired confirmation tokens: {}", e),
+            }
+        }
+    })
+}
+
+pub async fn delete_expired_tokens(
+    pool: &Pool<Postgres>,
+    token_ttl: Duration,
+) -> Result<u64, String> {
+    let result = sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE created_at <= $1",
+        cutoff(token_ttl)
+    )
+    .execute(pool)
+    .instrument(tracing::info_span!(
+        "delete expired subscription tokens query"
+    ))
+    .await
+    .map_err(|e| format!("Error deleting expired subscription tokens: {}", e))?;
+
+    Ok(result.rows_affected())
+}
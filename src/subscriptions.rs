@@ -0,0 +1,93 @@
+//! src/subscriptions.rs
+//!
+//! Persistence of the subscription status state machine defined by
+//! [`SubscriptionStatus`].
+
+use chrono::Utc;
+use sqlx::{Postgres, Transaction};
+use tracing::{info, Instrument};
+use uuid::Uuid;
+
+use crate::domain::subscriber::{SubscriberError, SubscriptionStatus};
+
+/// Moves subscriber `subscriber_id` to status `next` and records the change in
+/// `subscription_status_history`, as part of `transaction`.
+///
+/// The subscription row stays locked until the transaction ends, so
+/// concurrent transitions are applied one after the other. Moving to the
+/// status the subscriber already has is a no-op. Returns the previous status,
+/// or `None` if there is no such subscriber.
+pub async fn transition_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+) -> Result<Option<SubscriptionStatus>, SubscriberError> {
+    let current = sqlx::query!(
+        r#"
+        SELECT status as "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .instrument(tracing::info_span!("lock subscription status query"))
+    .await
+    .map_err(SubscriberError::DatabaseError)?;
+
+    let Some(current) = current.map(|record| record.status) else {
+        return Ok(None);
+    };
+
+    if current == next {
+        return Ok(Some(current));
+    }
+    if !current.can_transition_to(next) {
+        return Err(SubscriberError::InvalidStatusTransition(current, next));
+    }
+
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $2 WHERE id = $1",
+        subscriber_id,
+        next as SubscriptionStatus
+    )
+    .execute(&mut **transaction)
+    .instrument(tracing::info_span!("update subscription status query"))
+    .await
+    .map_err(SubscriberError::DatabaseError)?;
+
+    record_status_change(transaction, subscriber_id, Some(current), next).await?;
+
+    info!("Subscription status changed from {} to {}", current, next);
+    Ok(Some(current))
+}
+
+/// Appends a change from `from` to `to` to `subscription_status_history`,
+/// `from` being `None` for the status a subscription is created with.
+pub async fn record_status_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    from: Option<SubscriptionStatus>,
+    to: SubscriptionStatus,
+) -> Result<(), SubscriberError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_status_history
+            (subscriber_id, from_status, to_status, changed_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        subscriber_id,
+        from as Option<SubscriptionStatus>,
+        to as SubscriptionStatus,
+        Utc::now()
+    )
+    .execute(&mut **transaction)
+    .instrument(tracing::info_span!(
+        "record subscription status change query"
+    ))
+    .await
+    .map_err(SubscriberError::DatabaseError)?;
+
+    Ok(())
+}
@@ -1,14 +1,18 @@
 use askama::Template;
 
+use crate::routes::SubscriberRow;
+
 #[derive(Template)]
 #[template(path = "confirmation/email.html")]
 pub struct ConfirmationEmailHtmlTemplate<'a> {
+    pub base_url: &'a str,
     pub token: &'a str,
 }
 
 #[derive(Template)]
 #[template(path = "confirmation/email.txt")]
 pub struct ConfirmationEmailTxtTemplate<'a> {
+    pub base_url: &'a str,
     pub token: &'a str,
 }
 
@@ -22,4 +26,110 @@ pub struct HomeTemplate {}
 
 #[derive(Template)]
 #[template(path = "login.html")]
-pub struct LoginTemplate {}
+pub struct LoginTemplate<'a> {
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "login_two_factor.html")]
+pub struct LoginTwoFactorTemplate<'a> {
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+pub struct AdminDashboardTemplate<'a> {
+    pub username: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter.html")]
+pub struct AdminNewsletterTemplate<'a> {
+    pub idempotency_key: &'a str,
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+pub struct AdminPasswordTemplate<'a> {
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/two_factor.html")]
+pub struct AdminTwoFactorTemplate<'a> {
+    pub enabled: bool,
+    pub secret: &'a str,
+    pub otpauth_uri: &'a str,
+    /// Base64 encoded PNG of `otpauth_uri`.
+    pub qr_code: &'a str,
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/recovery_codes.html")]
+pub struct AdminRecoveryCodesTemplate<'a> {
+    pub recovery_codes: &'a [String],
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+pub struct AdminSubscribersTemplate<'a> {
+    pub subscribers: &'a [SubscriberRow],
+    pub next_cursor: Option<&'a str>,
+    pub status: Option<&'a str>,
+    pub q: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter/footer.html")]
+pub struct NewsletterFooterHtmlTemplate<'a> {
+    pub link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter/footer.txt")]
+pub struct NewsletterFooterTxtTemplate<'a> {
+    pub link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe/confirm.html")]
+pub struct UnsubscribeConfirmTemplate<'a> {
+    pub token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe/done.html")]
+pub struct UnsubscribeDoneTemplate {}
+
+#[derive(Template)]
+#[template(path = "password_reset/forgot.html")]
+pub struct ForgotPasswordTemplate<'a> {
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/reset.html")]
+pub struct ResetPasswordTemplate<'a> {
+    pub token: &'a str,
+    pub messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/email.html")]
+pub struct PasswordResetEmailHtmlTemplate<'a> {
+    pub base_url: &'a str,
+    pub token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/email.txt")]
+pub struct PasswordResetEmailTxtTemplate<'a> {
+    pub base_url: &'a str,
+    pub token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset/subject.txt")]
+pub struct PasswordResetEmailSubject {}
@@ -0,0 +1,40 @@
+//! src/time.rs
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// The instant `age` ago, or the Unix epoch for ages reaching further back.
+///
+/// Anything created before `cutoff(ttl)` has expired.
+pub fn cutoff(age: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(age)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .map_or(DateTime::<Utc>::UNIX_EPOCH, |cutoff| {
+            cutoff.max(DateTime::<Utc>::UNIX_EPOCH)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::time::cutoff;
+
+    #[test]
+    fn test_cutoff_is_in_the_past() {
+        let before = Utc::now();
+        let cutoff = cutoff(Duration::from_secs(60));
+        let after = Utc::now();
+        assert!(cutoff >= before - chrono::Duration::seconds(60));
+        assert!(cutoff <= after - chrono::Duration::seconds(60));
+    }
+
+    #[test]
+    fn test_cutoff_saturates_at_epoch() {
+        assert_eq!(DateTime::<Utc>::UNIX_EPOCH, cutoff(Duration::MAX));
+    }
+}
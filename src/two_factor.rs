@@ -0,0 +1,535 @@
+//! src/two_factor.rs
+//!
+//! Optional second factor for admin users: RFC 6238 time-based one-time
+//! passwords, with single-use recovery codes for a lost authenticator.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use sha3::{Digest, Sha3_256};
+use sqlx::postgres::types::PgInterval;
+use sqlx::{Pool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::config::TwoFactorConfig;
+
+/// Shown as the account's provider in authenticator apps.
+const ISSUER: &str = "zero2prod";
+/// The parameters every authenticator app supports.
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// Codes of the previous and next step are accepted too, for clock drift.
+const SKEW_STEPS: u64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Without characters that are easily mistaken for one another.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// 31^10, i.e. about 49 bits of entropy.
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Wrong codes in a row after which the second factor is locked.
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+/// How long the second factor stays locked, refusing even right codes.
+const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// AES-GCM nonces are 96 bits.
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts authenticator app secrets for the database, so that a copy of
+/// the database alone does not give away anyone's second factor.
+#[derive(Clone)]
+pub struct SecretCipher(Aes256Gcm);
+
+impl SecretCipher {
+    pub fn new(config: &TwoFactorConfig) -> Result<Self, String> {
+        let key = config
+            .encryption_key()
+            .map_err(|e| format!("Invalid two_factor.encryption_key: {}", e))?;
+        Aes256Gcm::new_from_slice(&key)
+            .map(Self)
+            .map_err(|e| format!("Invalid two_factor.encryption_key: {}", e))
+    }
+
+    /// A random nonce followed by the ciphertext. The user id is
+    /// authenticated along with it, so a secret copied to another user's
+    /// row does not decrypt.
+    fn encrypt(&self, secret: &str, user_id: Uuid) -> Result<Vec<u8>, String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: secret.as_bytes(),
+            aad: user_id.as_bytes(),
+        };
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, payload)
+            .map_err(|_| "Error encrypting TOTP secret".to_string())?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, encrypted: &[u8], user_id: Uuid) -> Result<String, String> {
+        if encrypted.len() < NONCE_LENGTH {
+            return Err("Encrypted TOTP secret is truncated".into());
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: user_id.as_bytes(),
+        };
+        let secret = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                "Error decrypting TOTP secret, two_factor.encryption_key may have changed"
+                    .to_string()
+            })?;
+        String::from_utf8(secret).map_err(|e| format!("Invalid TOTP secret: {}", e))
+    }
+}
+
+/// A fresh 160 bit secret, base32 encoded as authenticator apps expect.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    Secret::Raw(secret.to_vec()).to_encoded().to_string()
+}
+
+fn totp(secret: &str, username: &str) -> Result<TOTP, String> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| format!("Invalid TOTP secret: {:?}", e))?;
+    // ':' separates the issuer from the account name in the URI.
+    let account_name = username.replace(':', "");
+
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW_STEPS as u8,
+        STEP_SECONDS,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name,
+    )
+    .map_err(|e| format!("Invalid TOTP parameters: {}", e))
+}
+
+/// The `otpauth://` URI authenticator apps are set up with.
+pub fn otpauth_uri(secret: &str, username: &str) -> Result<String, String> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+/// [`otpauth_uri`] as a QR code: a base64 encoded PNG.
+pub fn qr_code(secret: &str, username: &str) -> Result<String, String> {
+    totp(secret, username)?.get_qr_base64()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before 1970")
+        .as_secs()
+}
+
+/// The step `code` was generated for, if it is valid at `time`.
+fn matching_step(totp: &TOTP, code: &str, time: u64) -> Option<u64> {
+    let current_step = time / STEP_SECONDS;
+    // Every candidate is compared, so the time taken does not depend on
+    // which one matched.
+    (current_step.saturating_sub(SKEW_STEPS)..=current_step + SKEW_STEPS).fold(
+        None,
+        |matched, step| {
+            let expected = totp.generate(step * STEP_SECONDS);
+            if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) {
+                Some(step)
+            } else {
+                matched
+            }
+        },
+    )
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Recovery codes as shown to the user, e.g. `abcde-fghjk`.
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LENGTH)
+                .map(|_| {
+                    RECOVERY_CODE_ALPHABET[OsRng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            let (first, second) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+            format!("{}-{}", first, second)
+        })
+        .collect()
+}
+
+/// Only the hash of a recovery code is stored. Case, dashes and spaces do
+/// not matter when one is entered.
+fn hash_recovery_code(code: &str) -> Vec<u8> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    Sha3_256::digest(normalized.as_bytes()).to_vec()
+}
+
+/// Whether user `user_id` has to enter a second factor to log in.
+pub async fn is_enabled(pool: &Pool<Postgres>, user_id: Uuid) -> Result<bool, String> {
+    sqlx::query_scalar!(
+        r#"SELECT totp_secret IS NOT NULL AS "enabled!" FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .instrument(tracing::info_span!("get two-factor status query"))
+    .await
+    .map_err(|e| format!("Error looking up two-factor status: {}", e))
+    .map(|enabled| enabled.unwrap_or(false))
+}
+
+/// Turns on the second factor for `user_id` if `code` is valid for
+/// `secret`, proving the authenticator app was set up correctly.
+///
+/// The secret is stored encrypted with `cipher`. Returns the new recovery
+/// codes, which replace any previous ones and are not stored anywhere in
+/// clear, or `None` if the code is wrong.
+pub async fn enable(
+    pool: &Pool<Postgres>,
+    cipher: &SecretCipher,
+    user_id: Uuid,
+    username: &str,
+    secret: &str,
+    code: &str,
+) -> Result<Option<Vec<String>>, String> {
+    let code = code.trim();
+    let step = match is_totp_code(code) {
+        true => matching_step(&totp(secret, username)?, code, now()),
+        false => None,
+    };
+    let Some(step) = step else {
+        return Ok(None);
+    };
+
+    let encrypted_secret = cipher.encrypt(secret, user_id)?;
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1,
+            totp_last_used_step = $2,
+            totp_failed_attempts = 0,
+            totp_locked_until = NULL
+        WHERE id = $3
+        "#,
+        encrypted_secret,
+        step as i64,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("enable two-factor query"))
+    .await
+    .map_err(|e| format!("Error enabling two-factor authentication: {}", e))?;
+
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("delete recovery codes query"))
+    .await
+    .map_err(|e| format!("Error deleting recovery codes: {}", e))?;
+
+    let code_hashes: Vec<Vec<u8>> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::bytea[])
+        "#,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("add recovery codes query"))
+    .await
+    .map_err(|e| format!("Error storing recovery codes: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing transaction: {}", e))?;
+
+    Ok(Some(recovery_codes))
+}
+
+/// Turns off the second factor for `user_id` and drops their recovery codes.
+pub async fn disable(pool: &Pool<Postgres>, user_id: Uuid) -> Result<(), String> {
+    // The recovery codes are useless without a secret, and stale ones must
+    // not come back to life when the second factor is turned on again.
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL,
+            totp_last_used_step = NULL,
+            totp_failed_attempts = 0,
+            totp_locked_until = NULL
+        WHERE id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("disable two-factor query"))
+    .await
+    .map_err(|e| format!("Error disabling two-factor authentication: {}", e))?;
+
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("delete recovery codes query"))
+    .await
+    .map_err(|e| format!("Error deleting recovery codes: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing transaction: {}", e))
+}
+
+/// The outcome of [`verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Valid,
+    Invalid,
+    /// Too many wrong codes were entered recently; no code is checked until
+    /// the lockout ends.
+    LockedOut,
+    /// The user was disabled after entering their password.
+    Disabled,
+}
+
+/// Checks the second factor of `user_id`: either a code from their
+/// authenticator app or one of their recovery codes.
+///
+/// Each app code is accepted once, and each recovery code is used up. Wrong
+/// codes are counted per user rather than per login, so entering the
+/// password again does not buy more guesses.
+pub async fn verify(
+    pool: &Pool<Postgres>,
+    cipher: &SecretCipher,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Verification, String> {
+    let code = code.trim();
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+
+    // The row lock serializes concurrent logins, so a code cannot be used
+    // twice and no guess goes uncounted by racing two requests.
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            username,
+            totp_secret,
+            totp_last_used_step,
+            totp_failed_attempts,
+            COALESCE(totp_locked_until > now(), false) AS "locked!",
+            disabled_at IS NOT NULL AS "disabled!"
+        FROM users
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .instrument(tracing::info_span!("get two-factor secret query"))
+    .await
+    .map_err(|e| format!("Error looking up two-factor secret: {}", e))?;
+    let Some(user) = user else {
+        return Ok(Verification::Invalid);
+    };
+    if user.disabled {
+        return Ok(Verification::Disabled);
+    }
+    let Some(encrypted_secret) = user.totp_secret else {
+        return Ok(Verification::Invalid);
+    };
+    if user.locked {
+        return Ok(Verification::LockedOut);
+    }
+
+    let valid = match is_totp_code(code) {
+        true => {
+            let secret = cipher.decrypt(&encrypted_secret, user_id)?;
+            let step =
+                matching_step(&totp(&secret, &user.username)?, code, now()).filter(|&step| {
+                    user.totp_last_used_step
+                        .is_none_or(|last_used| step as i64 > last_used)
+                });
+            if let Some(step) = step {
+                sqlx::query!(
+                    "UPDATE users SET totp_last_used_step = $1 WHERE id = $2",
+                    step as i64,
+                    user_id
+                )
+                .execute(&mut *transaction)
+                .instrument(tracing::info_span!("update last used step query"))
+                .await
+                .map_err(|e| format!("Error storing last used step: {}", e))?;
+            }
+            step.is_some()
+        }
+        false => use_recovery_code(&mut transaction, user_id, code).await?,
+    };
+
+    let failed_attempts = match valid {
+        true => 0,
+        false => user.totp_failed_attempts + 1,
+    };
+    let locked_out = failed_attempts >= MAX_FAILED_ATTEMPTS;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_failed_attempts = $1,
+            totp_locked_until = CASE WHEN $2 THEN now() + $3 ELSE NULL END
+        WHERE id = $4
+        "#,
+        if locked_out { 0 } else { failed_attempts },
+        locked_out,
+        PgInterval::try_from(LOCKOUT).expect("The lockout fits an interval"),
+        user_id
+    )
+    .execute(&mut *transaction)
+    .instrument(tracing::info_span!("update failed attempts query"))
+    .await
+    .map_err(|e| format!("Error storing failed attempts: {}", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Error committing transaction: {}", e))?;
+
+    Ok(match (valid, locked_out) {
+        (true, _) => Verification::Valid,
+        (false, true) => Verification::LockedOut,
+        (false, false) => Verification::Invalid,
+    })
+}
+
+async fn use_recovery_code(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, String> {
+    // Deleting the code is what makes it single-use.
+    let used = sqlx::query!(
+        r#"
+        DELETE FROM totp_recovery_codes
+        WHERE user_id = $1 AND code_hash = $2
+        RETURNING user_id
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .fetch_optional(&mut **transaction)
+    .instrument(tracing::info_span!("use recovery code query"))
+    .await
+    .map_err(|e| format!("Error using recovery code: {}", e))?
+    .is_some();
+
+    Ok(used)
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use super::{
+        generate_recovery_codes, generate_secret, hash_recovery_code, matching_step, totp,
+        SecretCipher, STEP_SECONDS,
+    };
+    use crate::config::TwoFactorConfig;
+
+    fn cipher(key: [u8; 32]) -> SecretCipher {
+        SecretCipher::new(&TwoFactorConfig {
+            encryption_key: Secret::new(base64::engine::general_purpose::STANDARD.encode(key)),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_secrets_decrypt_only_for_their_user_and_key() {
+        let cipher_a = cipher([1; 32]);
+        let cipher_b = cipher([2; 32]);
+        let secret = generate_secret();
+        let user_id = Uuid::new_v4();
+
+        let encrypted = cipher_a.encrypt(&secret, user_id).unwrap();
+        assert_ne!(encrypted, cipher_a.encrypt(&secret, user_id).unwrap());
+        assert_eq!(secret, cipher_a.decrypt(&encrypted, user_id).unwrap());
+
+        assert!(cipher_a.decrypt(&encrypted, Uuid::new_v4()).is_err());
+        assert!(cipher_b.decrypt(&encrypted, user_id).is_err());
+        assert!(cipher_a.decrypt(&encrypted[..8], user_id).is_err());
+    }
+
+    #[test]
+    fn test_codes_of_adjacent_steps_are_accepted() {
+        let totp = totp(&generate_secret(), "admin").unwrap();
+        let time = 1_700_000_000;
+        let step = time / STEP_SECONDS;
+
+        for offset in [-1i64, 0, 1] {
+            let code_step = (step as i64 + offset) as u64;
+            let code = totp.generate(code_step * STEP_SECONDS);
+            assert_eq!(Some(code_step), matching_step(&totp, &code, time));
+        }
+
+        let stale_code = totp.generate((step - 2) * STEP_SECONDS);
+        let current_code = totp.generate(time);
+        // Two steps may coincidentally share a code.
+        if stale_code != current_code {
+            assert_eq!(None, matching_step(&totp, &stale_code, time));
+        }
+    }
+
+    #[test]
+    fn test_recovery_codes_are_distinct_and_forgiving_to_type() {
+        let codes = generate_recovery_codes();
+
+        let mut hashes: Vec<_> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+        hashes.sort();
+        hashes.dedup();
+        assert_eq!(codes.len(), hashes.len());
+
+        let code = &codes[0];
+        let retyped = code.to_uppercase().replace('-', " ");
+        assert_eq!(hash_recovery_code(code), hash_recovery_code(&retyped));
+    }
+}
@@ -0,0 +1,114 @@
+//! src/unsubscribe.rs
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha3::Sha3_256;
+use uuid::Uuid;
+
+use crate::domain::subscriber::SubscriberError;
+
+/// Separates unsubscribe signatures from anything else signed with the same
+/// secret.
+const CONTEXT: &[u8] = b"unsubscribe:";
+
+/// Signs and verifies the per-subscriber one-click unsubscribe links that
+/// go out with every newsletter issue.
+///
+/// A token is `<subscriber id>.<signature>`, so the link needs no database
+/// state and cannot be forged for somebody else's subscription.
+#[derive(Clone)]
+pub struct UnsubscribeLinks {
+    base_url: String,
+    hmac_secret: Secret<String>,
+}
+
+impl UnsubscribeLinks {
+    pub fn new(base_url: String, hmac_secret: Secret<String>) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn link(&self, subscriber_id: Uuid) -> String {
+        format!(
+            "{}/unsubscribe?token={}",
+            self.base_url,
+            self.token(subscriber_id)
+        )
+    }
+
+    pub fn token(&self, subscriber_id: Uuid) -> String {
+        let signature = self.mac(subscriber_id).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            subscriber_id,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Returns the subscriber id `token` was issued for.
+    pub fn verify(&self, token: &str) -> Result<Uuid, SubscriberError> {
+        let invalid = || SubscriberError::InvalidToken;
+
+        let (subscriber_id, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|_| invalid())?;
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid())?;
+
+        self.mac(subscriber_id)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        Ok(subscriber_id)
+    }
+
+    fn mac(&self, subscriber_id: Uuid) -> Hmac<Sha3_256> {
+        let mut mac = Hmac::<Sha3_256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(CONTEXT);
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use crate::unsubscribe::UnsubscribeLinks;
+
+    fn links(secret: &str) -> UnsubscribeLinks {
+        UnsubscribeLinks::new("https://zero2prod.xyz".into(), Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        assert_ok_eq!(links.verify(&links.token(subscriber_id)), subscriber_id);
+    }
+
+    #[test]
+    fn test_token_for_another_subscriber_is_rejected() {
+        let links = links("secret");
+        let token = links.token(Uuid::new_v4());
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_err!(links.verify(&format!("{}.{}", Uuid::new_v4(), signature)));
+    }
+
+    #[test]
+    fn test_token_signed_with_another_secret_is_rejected() {
+        let token = links("other").token(Uuid::new_v4());
+        assert_err!(links("secret").verify(&token));
+    }
+
+    #[test]
+    fn test_malformed_token_is_rejected() {
+        assert_err!(links("secret").verify("not-a-token"));
+    }
+}
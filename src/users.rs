@@ -0,0 +1,197 @@
+//! src/users.rs
+//!
+//! Admin user management, behind the `user` subcommands of the binary.
+
+use chrono::{DateTime, Utc};
+use lettre::Address;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::auth::compute_password_hash;
+use crate::config::PasswordHashConfig;
+use crate::session::delete_user_sessions;
+use crate::two_factor;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    /// Where password reset links are sent.
+    pub email: Option<String>,
+    /// When the user was disabled; disabled users cannot log in.
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+/// Creates a user who can log in with `password` and returns their id.
+pub async fn create(
+    pool: &Pool<Postgres>,
+    username: &str,
+    email: Option<&str>,
+    password: &Secret<String>,
+    config: &PasswordHashConfig,
+) -> Result<Uuid, String> {
+    if username.trim().is_empty() {
+        return Err("Username must not be empty".into());
+    }
+    if let Some(email) = email {
+        parse_email(email)?;
+    }
+    let password_hash = hash_password(password, config).await?;
+    let user_id = Uuid::new_v4();
+
+    sqlx::query!(
+        "INSERT INTO users (id, username, email, password_hash) VALUES ($1, $2, $3, $4)",
+        user_id,
+        username,
+        email,
+        password_hash
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => {
+            format!("User {} or email address already exists", username)
+        }
+        _ => format!("Error creating user: {}", e),
+    })?;
+
+    Ok(user_id)
+}
+
+/// Replaces the password of `username` and logs them out everywhere.
+pub async fn set_password(
+    pool: &Pool<Postgres>,
+    username: &str,
+    password: &Secret<String>,
+    config: &PasswordHashConfig,
+) -> Result<(), String> {
+    let user_id = find_id(pool, username).await?;
+    change_password(pool, user_id, password, config).await?;
+
+    delete_user_sessions(pool, user_id)
+        .await
+        .map_err(|e| format!("Error logging user out: {}", e))?;
+
+    Ok(())
+}
+
+/// Replaces the password of user `user_id`. Their sessions are left to the
+/// caller to end.
+pub async fn change_password(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    password: &Secret<String>,
+    config: &PasswordHashConfig,
+) -> Result<(), String> {
+    let password_hash = hash_password(password, config).await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Error updating password: {}", e))?;
+
+    Ok(())
+}
+
+/// Sets the address password reset links are sent to, or removes it.
+pub async fn set_email(
+    pool: &Pool<Postgres>,
+    username: &str,
+    email: Option<&str>,
+) -> Result<(), String> {
+    if let Some(email) = email {
+        parse_email(email)?;
+    }
+
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE username = $2 RETURNING id",
+        email,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => {
+            "Email address is used by another user".to_string()
+        }
+        _ => format!("Error updating email: {}", e),
+    })?
+    .ok_or_else(|| format!("No user named {}", username))?;
+
+    Ok(())
+}
+
+/// Stops `username` from logging in and ends their sessions. Disabling an
+/// already disabled user keeps the original date.
+pub async fn disable(pool: &Pool<Postgres>, username: &str) -> Result<(), String> {
+    let user_id = sqlx::query_scalar!(
+        "UPDATE users SET disabled_at = COALESCE(disabled_at, now())
+        WHERE username = $1
+        RETURNING id",
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Error disabling user: {}", e))?
+    .ok_or_else(|| format!("No user named {}", username))?;
+
+    delete_user_sessions(pool, user_id)
+        .await
+        .map_err(|e| format!("Error logging user out: {}", e))?;
+
+    Ok(())
+}
+
+/// Turns off the second factor of `username`, e.g. after they lost both
+/// their authenticator and their recovery codes.
+pub async fn reset_two_factor(pool: &Pool<Postgres>, username: &str) -> Result<(), String> {
+    let user_id = find_id(pool, username).await?;
+    two_factor::disable(pool, user_id).await
+}
+
+/// Every user, ordered by username.
+pub async fn list(pool: &Pool<Postgres>) -> Result<Vec<User>, String> {
+    sqlx::query_as!(
+        User,
+        "SELECT id, username, email, disabled_at FROM users ORDER BY username"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Error listing users: {}", e))
+}
+
+async fn find_id(pool: &Pool<Postgres>, username: &str) -> Result<Uuid, String> {
+    sqlx::query_scalar!("SELECT id FROM users WHERE username = $1", username)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Error looking up user: {}", e))?
+        .ok_or_else(|| format!("No user named {}", username))
+}
+
+fn parse_email(email: &str) -> Result<(), String> {
+    email
+        .parse::<Address>()
+        .map(|_| ())
+        .map_err(|e| format!("Invalid email address {:?}: {}", email, e))
+}
+
+/// Hashes `password` on the blocking thread pool.
+pub(crate) async fn hash_password(
+    password: &Secret<String>,
+    config: &PasswordHashConfig,
+) -> Result<String, String> {
+    if password.expose_secret().is_empty() {
+        return Err("Password must not be empty".into());
+    }
+    let password = password.clone();
+    let config = config.clone();
+
+    tokio::task::spawn_blocking(move || compute_password_hash(&password, &config))
+        .await
+        .map_err(|e| format!("Error hashing password: {}", e))?
+}
@@ -0,0 +1,120 @@
+//! tests/api/admin.rs
+
+use crate::test_app::spawn;
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::lorem::en::{Paragraph, Sentence};
+use fake::faker::name::en::FirstName;
+use fake::uuid::UUIDv4;
+use fake::Fake;
+
+#[tokio::test]
+async fn login_sets_session_cookie_and_redirects_to_dashboard() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+
+    let session_cookie = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap())
+        .find(|cookie| cookie.starts_with("id="))
+        .expect("Missing session cookie");
+    assert!(session_cookie.contains("HttpOnly"));
+    assert!(session_cookie.contains("SameSite=Strict"));
+
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("Welcome admin"));
+}
+
+#[tokio::test]
+async fn dashboard_requires_login() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn logout_clears_session() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+
+    let response = test_app.logout().await.expect("Failed to log out");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!(303, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletter_form_publishes_with_session() {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+    let idempotency_key: String = UUIDv4.fake();
+
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+
+    let response = test_app
+        .publish_newsletter_form(&html, &text, &subject, &idempotency_key)
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/admin/newsletter", response.headers()["Location"]);
+
+    let html_page = test_app.get_newsletter_form_html().await;
+    assert!(html_page.contains("The newsletter issue has been accepted"));
+
+    test_app.wait_for_deliveries().await;
+
+    let newsletters_sent = test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(_, email_html, _)| email_html.starts_with(&html))
+        .count();
+    assert_eq!(1, newsletters_sent);
+}
+
+#[tokio::test]
+async fn newsletter_form_requires_login() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app
+        .publish_newsletter_form("<p>html</p>", "text", "subject", "key")
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+    assert!(test_app.get_sent_emails().is_empty());
+}
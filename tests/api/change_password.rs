@@ -0,0 +1,126 @@
+//! tests/api/change_password.rs
+
+use crate::test_app::{spawn, TestApp};
+
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+async fn log_in_admin(test_app: &TestApp) {
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+/// A second browser, logged in as `username`.
+async fn other_browser(test_app: &TestApp, username: &str, password: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", test_app.address()))
+        .form(&[("username", username), ("password", password)])
+        .send()
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+    client
+}
+
+async fn dashboard_status(test_app: &TestApp, client: &reqwest::Client) -> u16 {
+    client
+        .get(format!("{}/admin/dashboard", test_app.address()))
+        .send()
+        .await
+        .expect("Failed to get dashboard")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn change_password_requires_login() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app
+        .change_password("password", NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to change password");
+
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn password_is_changed_and_other_sessions_end() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    let other_browser = other_browser(&test_app, "admin", "password").await;
+    assert_eq!(200, dashboard_status(&test_app, &other_browser).await);
+
+    let response = test_app
+        .change_password("password", NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to change password");
+    assert_eq!("/admin/password", response.headers()["Location"]);
+
+    let html_page = test_app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Your password has been changed</i></p>"));
+    assert_eq!(303, dashboard_status(&test_app, &other_browser).await);
+
+    test_app.logout().await.expect("Failed to log out");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/login", response.headers()["Location"]);
+    let response = test_app
+        .login("admin", NEW_PASSWORD)
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn invalid_changes_are_refused() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+
+    let cases = [
+        (
+            "wrong-password",
+            NEW_PASSWORD,
+            NEW_PASSWORD,
+            "The current password is incorrect",
+        ),
+        (
+            "password",
+            NEW_PASSWORD,
+            "another password",
+            "the field values must match",
+        ),
+        ("password", "short", "short", "at least 12 characters"),
+        ("password", "password", "password", "must differ"),
+    ];
+    for (current, new, check, error) in cases {
+        let response = test_app
+            .change_password(current, new, check)
+            .await
+            .expect("Failed to change password");
+        assert_eq!("/admin/password", response.headers()["Location"]);
+
+        let html_page = test_app.get_change_password_html().await;
+        assert!(html_page.contains(error), "{}", error);
+    }
+
+    test_app.logout().await.expect("Failed to log out");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
@@ -1,5 +1,6 @@
-use crate::test_app::spawn;
+use crate::test_app::{spawn, TOKEN_TTL};
 use fake::{faker, uuid::UUIDv4, Fake};
+use zero2prod::domain::subscriber::SubscriptionStatus;
 
 #[tokio::test]
 async fn confirm_returns_200_with_valid_token() {
@@ -37,6 +38,9 @@ async fn confirm_returns_400_with_invalid_token() {
         .expect("Failed to execute request.");
 
     assert_eq!(400, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    assert!(body.contains("The link is invalid or has expired"));
+    assert!(!body.contains(&uuid));
 }
 
 #[tokio::test]
@@ -50,3 +54,132 @@ async fn confirm_returns_400_with_no_token() {
 
     assert_eq!(400, response.status().as_u16());
 }
+
+#[tokio::test]
+async fn confirm_records_status_history() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(name.clone(), email.clone())
+        .await;
+
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    assert_eq!(
+        vec![
+            (None, SubscriptionStatus::Pending),
+            (
+                Some(SubscriptionStatus::Pending),
+                SubscriptionStatus::Confirmed
+            ),
+        ],
+        test_app.get_status_history(subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn confirm_returns_409_for_unsubscribed_subscriber() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to post subscription");
+
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    let subscription_token = test_app.get_subscription_token(subscriber_id).await;
+    test_app
+        .set_subscription_status(&email, "unsubscribed")
+        .await
+        .unwrap();
+
+    let response = test_app
+        .confirm_subscription(&subscription_token)
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(
+        SubscriptionStatus::Unsubscribed,
+        test_app.get_subscription_status(&email).await
+    );
+}
+
+#[tokio::test]
+async fn unknown_status_is_rejected_by_the_database() {
+    let test_app = spawn().await.unwrap();
+
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(faker::name::en::FirstName().fake(), email.clone())
+        .await
+        .expect("Failed to post subscription");
+
+    assert!(test_app
+        .set_subscription_status(&email, "confirmd")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn confirm_returns_400_with_expired_token() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to post subscription");
+
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    let subscription_token = test_app.get_subscription_token(subscriber_id).await;
+    test_app
+        .age_subscription_tokens(subscriber_id, TOKEN_TTL * 2)
+        .await;
+
+    let response = test_app
+        .confirm_subscription(&subscription_token)
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    assert!(!body.contains(&subscription_token));
+    assert_eq!(
+        SubscriptionStatus::Pending,
+        test_app.get_subscription_status(&email).await
+    );
+}
+
+#[tokio::test]
+async fn expired_tokens_are_cleaned_up() {
+    let test_app = spawn().await.unwrap();
+
+    let mut subscriber_ids = Vec::new();
+    for _ in 0..2 {
+        let name: String = faker::name::en::FirstName().fake();
+        let email: String = faker::internet::en::SafeEmail().fake();
+        test_app
+            .create_subscription(name.clone(), email.clone())
+            .await
+            .expect("Failed to post subscription");
+        subscriber_ids.push(test_app.get_subscription(&name, &email).await);
+    }
+    test_app
+        .age_subscription_tokens(subscriber_ids[0], TOKEN_TTL * 2)
+        .await;
+
+    assert_eq!(1, test_app.delete_expired_tokens().await);
+    assert_eq!(
+        0,
+        test_app.count_subscription_tokens(subscriber_ids[0]).await
+    );
+    assert_eq!(
+        1,
+        test_app.count_subscription_tokens(subscriber_ids[1]).await
+    );
+}
@@ -0,0 +1,212 @@
+//! tests/api/dead_letters.rs
+
+use crate::test_app::{spawn, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::lorem::en::{Paragraph, Sentence};
+use fake::faker::name::en::FirstName;
+use fake::Fake;
+use zero2prod::email::EmailError;
+
+async fn publish(test_app: &TestApp) -> String {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+
+    let response = test_app
+        .publish_newsletter(
+            Some(html.clone()),
+            Some(text),
+            Some(subject),
+            "admin",
+            Some("password"),
+        )
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(202, response.status().as_u16());
+
+    html
+}
+
+async fn dead_letters(test_app: &TestApp) -> Vec<serde_json::Value> {
+    let response = test_app
+        .get_dead_letters()
+        .await
+        .expect("Failed to list dead letters");
+    assert_eq!(200, response.status().as_u16());
+
+    response.json().await.expect("Invalid dead letters body")
+}
+
+fn newsletters_sent(test_app: &TestApp, html: &str) -> usize {
+    test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(_, email_html, _)| email_html.starts_with(html))
+        .count()
+}
+
+#[tokio::test]
+async fn transient_failure_is_retried() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+
+    test_app.email_service().fail_next(EmailError::Transport {
+        code: Some(421),
+        message: "Service not available".into(),
+    });
+    let html = publish(&test_app).await;
+    test_app.wait_for_deliveries().await;
+
+    assert_eq!(1, newsletters_sent(&test_app, &html));
+    assert!(dead_letters(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn permanent_failure_is_dead_lettered() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    let email: String = SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), email.clone())
+        .await;
+
+    test_app.email_service().fail_next(EmailError::Rejected {
+        code: Some(550),
+        message: "Mailbox unavailable".into(),
+    });
+    let html = publish(&test_app).await;
+    test_app.wait_for_deliveries().await;
+
+    assert_eq!(0, newsletters_sent(&test_app, &html));
+
+    let dead_letters = dead_letters(&test_app).await;
+    assert_eq!(1, dead_letters.len());
+    assert_eq!(email, dead_letters[0]["subscriber_email"]);
+    assert_eq!(1, dead_letters[0]["n_attempts"]);
+}
+
+#[tokio::test]
+async fn exhausted_retries_are_dead_lettered() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+
+    // The test app allows two retries, so the third failure is final.
+    for _ in 0..3 {
+        test_app.email_service().fail_next(EmailError::Transport {
+            code: None,
+            message: "Connection reset".into(),
+        });
+    }
+    let html = publish(&test_app).await;
+    test_app.wait_for_deliveries().await;
+
+    assert_eq!(0, newsletters_sent(&test_app, &html));
+
+    let dead_letters = dead_letters(&test_app).await;
+    assert_eq!(1, dead_letters.len());
+    assert_eq!(3, dead_letters[0]["n_attempts"]);
+}
+
+#[tokio::test]
+async fn replayed_dead_letter_is_delivered() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+
+    test_app.email_service().fail_next(EmailError::Rejected {
+        code: Some(550),
+        message: "Mailbox unavailable".into(),
+    });
+    let html = publish(&test_app).await;
+    test_app.wait_for_deliveries().await;
+
+    let issue_id = dead_letters(&test_app).await[0]["newsletter_issue_id"]
+        .as_str()
+        .expect("Missing newsletter issue id")
+        .to_string();
+
+    let response = test_app
+        .replay_dead_letters(&issue_id, None)
+        .await
+        .expect("Failed to replay dead letters");
+    assert_eq!(202, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(1, body["replayed"]);
+    assert_eq!(0, body["already_queued"]);
+
+    test_app.wait_for_deliveries().await;
+
+    assert_eq!(1, newsletters_sent(&test_app, &html));
+    assert!(dead_letters(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn dead_letter_of_a_queued_delivery_is_kept() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    let email: String = SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), email.clone())
+        .await;
+
+    test_app.email_service().fail_next(EmailError::Rejected {
+        code: Some(550),
+        message: "Mailbox unavailable".into(),
+    });
+    publish(&test_app).await;
+    test_app.wait_for_deliveries().await;
+
+    let issue_id = dead_letters(&test_app).await[0]["newsletter_issue_id"]
+        .as_str()
+        .expect("Missing newsletter issue id")
+        .to_string();
+    test_app.queue_delivery_later(&issue_id, &email).await;
+
+    let response = test_app
+        .replay_dead_letters(&issue_id, None)
+        .await
+        .expect("Failed to replay dead letters");
+    assert_eq!(202, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(0, body["replayed"]);
+    assert_eq!(1, body["already_queued"]);
+
+    let dead_letters = dead_letters(&test_app).await;
+    assert_eq!(1, dead_letters.len());
+    assert_eq!(email, dead_letters[0]["subscriber_email"]);
+}
+
+#[tokio::test]
+async fn dead_letters_require_login() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app
+        .get_dead_letters()
+        .await
+        .expect("Failed to list dead letters");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+}
@@ -9,7 +9,7 @@ async fn health_check_ok() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", test_app.address()))
+        .get(format!("{}/health_check", test_app.address()))
         .send()
         .await
         .expect("Failed to execute request.");
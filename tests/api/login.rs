@@ -0,0 +1,176 @@
+//! tests/api/login.rs
+
+use std::time::{Duration, Instant};
+
+use crate::test_app::{spawn, spawn_with, TestApp};
+
+/// Rounds of failed logins timed per username.
+const LATENCY_SAMPLES: usize = 10;
+
+#[tokio::test]
+async fn login_form_returns_html() {
+    let test_app = spawn().await.unwrap();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/login", test_app.address()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/html; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"<form action="/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn failed_login_redirects_with_one_shot_flash_message() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app
+        .login("unknown", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+
+    let html_page = test_app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn login_errors_cannot_be_injected_through_the_url() {
+    let test_app = spawn().await.unwrap();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!(
+            "{}/login?error=%3Cscript%3Ealert(1)%3C%2Fscript%3E",
+            test_app.address()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(!response.text().await.unwrap().contains("<script>"));
+}
+
+/// Times a failed login for each of `usernames`, taking turns so that both
+/// see the same load, and returns the sorted latencies of each username.
+async fn failed_login_latencies(test_app: &TestApp, usernames: [&str; 2]) -> [Vec<Duration>; 2] {
+    let mut latencies = [Vec::new(), Vec::new()];
+    for _ in 0..LATENCY_SAMPLES {
+        for (username, latencies) in usernames.iter().zip(latencies.iter_mut()) {
+            let started = Instant::now();
+            let response = test_app
+                .login(username, "wrong-password")
+                .await
+                .expect("Failed to log in");
+            latencies.push(started.elapsed());
+            assert_eq!("/login", response.headers()["Location"]);
+        }
+    }
+    for latencies in latencies.iter_mut() {
+        latencies.sort();
+    }
+    latencies
+}
+
+#[tokio::test]
+async fn unknown_and_known_usernames_take_as_long_to_reject() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+
+    let [known, unknown] = failed_login_latencies(&test_app, ["admin", "unknown"]).await;
+
+    let (known_min, known_max) = (known[0], known[LATENCY_SAMPLES - 1]);
+    let (unknown_min, unknown_max) = (unknown[0], unknown[LATENCY_SAMPLES - 1]);
+    assert!(
+        known_min <= unknown_max && unknown_min <= known_max,
+        "Latencies do not overlap: known {:?}, unknown {:?}",
+        known,
+        unknown
+    );
+
+    let known_median = known[LATENCY_SAMPLES / 2];
+    let unknown_median = unknown[LATENCY_SAMPLES / 2];
+    assert!(
+        known_median < unknown_median * 2 && unknown_median < known_median * 2,
+        "Median latencies differ too much: known {:?}, unknown {:?}",
+        known_median,
+        unknown_median
+    );
+}
+
+#[tokio::test]
+async fn login_upgrades_outdated_password_hash() {
+    let test_app = spawn_with(|config| {
+        config.password_hash_config.memory_kib = 8192;
+        config.password_hash_config.iterations = 1;
+    })
+    .await
+    .unwrap();
+    // Hashed with the default parameters.
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    let old_hash = test_app.get_password_hash("admin").await;
+
+    test_app
+        .login("admin", "wrong-password")
+        .await
+        .expect("Failed to log in");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+
+    let mut new_hash = old_hash.clone();
+    for _ in 0..50 {
+        new_hash = test_app.get_password_hash("admin").await;
+        if new_hash != old_hash {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(new_hash.contains("$m=8192,t=1,p=1$"), "{}", new_hash);
+
+    test_app.logout().await.expect("Failed to log out");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn login_keeps_up_to_date_password_hash() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    let old_hash = test_app.get_password_hash("admin").await;
+
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(old_hash, test_app.get_password_hash("admin").await);
+}
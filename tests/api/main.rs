@@ -1,6 +1,18 @@
+mod admin;
+mod change_password;
 mod confirm;
+mod dead_letters;
 mod health_check;
+mod login;
+mod migrations;
 mod mocks;
 mod newsletter;
+mod outbox;
+mod password_reset;
+mod shutdown;
 mod subscribe;
+mod subscribers;
 mod test_app;
+mod two_factor;
+mod unsubscribe;
+mod users;
@@ -0,0 +1,115 @@
+//! tests/api/migrations.rs
+
+use zero2prod::migrations::MIGRATOR;
+
+use crate::test_app::{spawn, spawn_with};
+
+#[tokio::test]
+async fn migrations_are_applied_on_startup_by_default() {
+    let test_app = spawn().await.unwrap();
+
+    let status = test_app.migration_status().await;
+
+    assert!(!status.is_empty());
+    assert!(status.iter().all(|m| m.applied && !m.checksum_mismatch));
+}
+
+#[tokio::test]
+async fn migrate_on_startup_can_be_turned_off() {
+    let test_app = spawn_with(|config| config.db_config.migrate_on_startup = false)
+        .await
+        .unwrap();
+
+    assert!(test_app.migration_status().await.iter().all(|m| !m.applied));
+
+    test_app.run_migrations().await;
+
+    assert!(test_app.migration_status().await.iter().all(|m| m.applied));
+}
+
+#[tokio::test]
+async fn migrations_with_down_scripts_can_be_reverted_and_reapplied() {
+    let test_app = spawn().await.unwrap();
+    let mut versions: Vec<i64> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .collect();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    // Newest first, the migrations with a down script up to the first one
+    // without, which reverting stops at.
+    let expected: Vec<i64> = versions
+        .iter()
+        .copied()
+        .take_while(|&version| {
+            MIGRATOR
+                .iter()
+                .any(|m| m.version == version && m.migration_type.is_down_migration())
+        })
+        .collect();
+    let first_kept = versions[expected.len()];
+    assert!(!expected.is_empty());
+
+    let mut reverted = Vec::new();
+    let error = loop {
+        match test_app.revert_migration().await {
+            Ok(Some(version)) => reverted.push(version),
+            Ok(None) => panic!("Ran out of migrations to revert"),
+            Err(e) => break e,
+        }
+    };
+
+    assert_eq!(expected, reverted);
+    assert!(error.contains("has no down script"));
+    assert!(error.starts_with(&format!("Migration {} ", first_kept)));
+    let status = test_app.migration_status().await;
+    assert!(status
+        .iter()
+        .all(|m| m.applied == (m.version <= first_kept)));
+
+    test_app.run_migrations().await;
+
+    let status = test_app.migration_status().await;
+    assert!(status.iter().all(|m| m.applied && !m.checksum_mismatch));
+}
+
+#[tokio::test]
+async fn lazy_startup_stops_on_a_migration_that_cannot_be_applied() {
+    let test_app = spawn().await.unwrap();
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = 1")
+        .execute(test_app.pool())
+        .await
+        .unwrap();
+
+    let mut relaunched = test_app
+        .respawn_with(|config| config.db_config.connect_lazily = true)
+        .await
+        .unwrap();
+
+    let error = relaunched.stopped().await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("previously applied but has been modified"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn lazy_startup_is_unhealthy_until_the_database_is_migrated() {
+    let test_app = spawn().await.unwrap();
+
+    let relaunched = test_app
+        .respawn_with(|config| {
+            config.db_config.connect_lazily = true;
+            config.db_config.port = 1;
+        })
+        .await
+        .unwrap();
+
+    let response = reqwest::get(format!("{}/health_check", relaunched.address()))
+        .await
+        .unwrap();
+    assert_eq!(503, response.status().as_u16());
+}
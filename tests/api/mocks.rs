@@ -1,9 +1,18 @@
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
-use zero2prod::email::{Email, EmailService};
+use std::time::Duration;
+use zero2prod::email::{Email, EmailError, EmailService};
 
 #[derive(Debug)]
 pub struct MockEmailService {
     pub sent_messages: Mutex<Vec<(String, String, String)>>,
+    /// Extra headers of every sent message, in the same order as `sent_messages`.
+    pub sent_headers: Mutex<Vec<Vec<(String, String)>>>,
+    failures: Mutex<VecDeque<EmailError>>,
+    send_delay: Mutex<Duration>,
+    sends_started: AtomicUsize,
 }
 
 impl Default for MockEmailService {
@@ -16,17 +25,60 @@ impl MockEmailService {
     pub fn new() -> Self {
         Self {
             sent_messages: Mutex::new(Vec::new()),
+            sent_headers: Mutex::new(Vec::new()),
+            failures: Mutex::new(VecDeque::new()),
+            send_delay: Mutex::new(Duration::ZERO),
+            sends_started: AtomicUsize::new(0),
         }
     }
+
+    /// Makes the next call to `send` fail with `error` instead of recording
+    /// the message. Calls queue up, so failures are returned in order.
+    pub fn fail_next(&self, error: EmailError) {
+        self.failures.lock().unwrap().push_back(error);
+    }
+
+    /// Makes every following call to `send` take `delay` before it
+    /// completes, like a slow mail server.
+    pub fn set_send_delay(&self, delay: Duration) {
+        *self.send_delay.lock().unwrap() = delay;
+    }
+
+    /// Waits until `count` calls to `send` have started.
+    pub async fn wait_for_sends_started(&self, count: usize) {
+        for _ in 0..100 {
+            if self.sends_started.load(Ordering::SeqCst) >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("{} sends were not started in time", count);
+    }
 }
 
+#[async_trait]
 impl EmailService for MockEmailService {
-    fn send(&self, message: Email) -> Result<(), String> {
+    async fn send(&self, message: Email<'_>) -> Result<(), EmailError> {
+        self.sends_started.fetch_add(1, Ordering::SeqCst);
+        let delay = *self.send_delay.lock().unwrap();
+        tokio::time::sleep(delay).await;
+
+        if let Some(error) = self.failures.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+
         self.sent_messages.lock().unwrap().push((
             message.to.to_owned(),
             message.html.to_owned(),
             message.plaintext.to_owned(),
         ));
+        self.sent_headers.lock().unwrap().push(
+            message
+                .headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        );
         Ok(())
     }
 }
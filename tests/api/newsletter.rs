@@ -4,12 +4,13 @@ use crate::test_app::spawn;
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::lorem::en::{Paragraph, Sentence};
 use fake::faker::name::en::FirstName;
+use fake::uuid::UUIDv4;
 use fake::Fake;
 
 #[tokio::test]
-async fn publish_newsletter_returns_200() {
+async fn publish_newsletter_returns_202() {
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
     let subject = Sentence(1..2).fake();
 
     let test_app = spawn().await.unwrap();
@@ -28,13 +29,13 @@ async fn publish_newsletter_returns_200() {
         )
         .await
         .expect("Failed to post subscription");
-    assert_eq!(200, response.status().as_u16());
+    assert_eq!(202, response.status().as_u16());
 }
 
 #[tokio::test]
 async fn missing_authorization_returns_401() {
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
     let subject = Sentence(1..2).fake();
 
     let test_app = spawn().await.unwrap();
@@ -49,7 +50,7 @@ async fn missing_authorization_returns_401() {
 #[tokio::test]
 async fn bad_password_returns_401() {
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
     let subject = Sentence(1..2).fake();
 
     let test_app = spawn().await.unwrap();
@@ -74,7 +75,7 @@ async fn bad_password_returns_401() {
 async fn publish_newsletter_returns_400_with_bad_html_text() {
     let subject: String = Sentence(1..2).fake();
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
 
     let test_cases = [
         (None, None, None, "Missing all fields"),
@@ -126,7 +127,7 @@ async fn publish_newsletter_returns_400_with_bad_html_text() {
 #[tokio::test]
 async fn newsletter_sent_to_confirmed_subscribers() {
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
     let subject = Sentence(1..2).fake();
 
     let test_app = spawn().await.unwrap();
@@ -134,9 +135,15 @@ async fn newsletter_sent_to_confirmed_subscribers() {
         .add_test_user("admin".to_string(), "password".to_string())
         .await;
 
+    for _ in 0..3 {
+        test_app
+            .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+            .await;
+    }
+
     let response = test_app
         .publish_newsletter(
-            Some(html),
+            Some(html.clone()),
             Some(text),
             Some(subject),
             "admin",
@@ -144,10 +151,17 @@ async fn newsletter_sent_to_confirmed_subscribers() {
         )
         .await
         .expect("Failed to post subscription");
-    assert_eq!(200, response.status().as_u16());
+    assert_eq!(202, response.status().as_u16());
+
+    test_app.wait_for_deliveries().await;
 
     let expected_emails = test_app.get_confirmed_subscriptions().await;
-    assert_eq!(expected_emails, test_app.get_sent_emails().len());
+    let newsletters_sent = test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(_, email_html, _)| email_html.starts_with(&html))
+        .count();
+    assert_eq!(expected_emails, newsletters_sent);
 }
 
 #[tokio::test]
@@ -168,7 +182,7 @@ async fn newsletter_not_sent_to_unconfirmed_subscribers() {
     assert_eq!(200, response.status().as_u16());
 
     let text: String = Paragraph(1..2).fake();
-    let html = String::from(format!("<p>{}</p>", text));
+    let html = format!("<p>{}</p>", text);
     let subject = Sentence(1..2).fake();
 
     let response = test_app
@@ -181,7 +195,9 @@ async fn newsletter_not_sent_to_unconfirmed_subscribers() {
         )
         .await
         .expect("Failed to post subscription");
-    assert_eq!(200, response.status().as_u16());
+    assert_eq!(202, response.status().as_u16());
+
+    test_app.wait_for_deliveries().await;
 
     let sent_emails = test_app.get_sent_emails();
 
@@ -202,3 +218,115 @@ async fn newsletter_not_sent_to_unconfirmed_subscribers() {
         "Expected confirmation email text"
     );
 }
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+    let idempotency_key: String = UUIDv4.fake();
+
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+
+    for _ in 0..2 {
+        let response = test_app
+            .publish_newsletter_with_idempotency_key(
+                Some(html.clone()),
+                Some(text.clone()),
+                Some(subject.clone()),
+                "admin",
+                Some("password"),
+                &idempotency_key,
+            )
+            .await
+            .expect("Failed to publish newsletter");
+        assert_eq!(202, response.status().as_u16());
+    }
+
+    test_app.wait_for_deliveries().await;
+
+    let newsletters_sent = test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(_, email_html, _)| email_html.starts_with(&html))
+        .count();
+    assert_eq!(1, newsletters_sent, "Expected the retry not to resend");
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submission_is_handled_gracefully() {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+    let idempotency_key: String = UUIDv4.fake();
+
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), SafeEmail().fake())
+        .await;
+
+    let first = test_app.publish_newsletter_with_idempotency_key(
+        Some(html.clone()),
+        Some(text.clone()),
+        Some(subject.clone()),
+        "admin",
+        Some("password"),
+        &idempotency_key,
+    );
+    let second = test_app.publish_newsletter_with_idempotency_key(
+        Some(html.clone()),
+        Some(text.clone()),
+        Some(subject.clone()),
+        "admin",
+        Some("password"),
+        &idempotency_key,
+    );
+    let (first, second) = tokio::join!(first, second);
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    assert_eq!(first.status(), second.status());
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+
+    test_app.wait_for_deliveries().await;
+
+    let newsletters_sent = test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(_, email_html, _)| email_html.starts_with(&html))
+        .count();
+    assert_eq!(1, newsletters_sent, "Expected a single delivery");
+}
+
+#[tokio::test]
+async fn invalid_idempotency_key_returns_400() {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+
+    let response = test_app
+        .publish_newsletter_with_idempotency_key(
+            Some(html),
+            Some(text),
+            Some(subject),
+            "admin",
+            Some("password"),
+            &"a".repeat(51),
+        )
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(400, response.status().as_u16());
+}
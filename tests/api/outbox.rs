@@ -0,0 +1,89 @@
+//! tests/api/outbox.rs
+
+use crate::test_app::spawn;
+use fake::{faker, Fake};
+use zero2prod::email::EmailError;
+
+#[tokio::test]
+async fn relayed_email_is_marked_sent() {
+    let test_app = spawn().await.unwrap();
+
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(faker::name::en::FirstName().fake(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+
+    test_app.wait_for_outbox().await;
+
+    assert_eq!(
+        vec![("sent".to_string(), 0, None)],
+        test_app.get_outbox_emails(&email).await
+    );
+    assert_eq!(1, test_app.get_sent_emails().len());
+}
+
+#[tokio::test]
+async fn transient_failure_is_retried_by_the_relay() {
+    let test_app = spawn().await.unwrap();
+    test_app.email_service().fail_next(EmailError::Transport {
+        code: Some(421),
+        message: "Service not available".into(),
+    });
+
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(faker::name::en::FirstName().fake(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+
+    test_app.wait_for_outbox().await;
+
+    let outbox_emails = test_app.get_outbox_emails(&email).await;
+    assert_eq!(1, outbox_emails.len());
+    let (status, n_retries, _) = &outbox_emails[0];
+    assert_eq!("sent", status);
+    assert_eq!(1, *n_retries);
+    assert_eq!(1, test_app.get_sent_emails().len());
+}
+
+#[tokio::test]
+async fn permanent_failure_is_marked_failed() {
+    let test_app = spawn().await.unwrap();
+    test_app.email_service().fail_next(EmailError::Rejected {
+        code: Some(550),
+        message: "Mailbox unavailable".into(),
+    });
+
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(faker::name::en::FirstName().fake(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+
+    test_app.wait_for_outbox().await;
+
+    let outbox_emails = test_app.get_outbox_emails(&email).await;
+    assert_eq!(1, outbox_emails.len());
+    let (status, _, last_error) = &outbox_emails[0];
+    assert_eq!("failed", status);
+    assert!(last_error
+        .as_deref()
+        .unwrap()
+        .contains("550 Mailbox unavailable"));
+    assert!(test_app.get_sent_emails().is_empty());
+}
+
+#[tokio::test]
+async fn rejected_subscription_queues_no_email() {
+    let test_app = spawn().await.unwrap();
+
+    let email: String = faker::internet::en::SafeEmail().fake();
+    let response = test_app
+        .create_subscription("".into(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(400, response.status().as_u16());
+
+    assert!(test_app.get_outbox_emails(&email).await.is_empty());
+}
@@ -0,0 +1,199 @@
+//! tests/api/password_reset.rs
+
+use std::time::Duration;
+
+use zero2prod::users;
+
+use crate::test_app::{spawn, TestApp};
+
+const EMAIL: &str = "admin@example.com";
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+async fn add_admin_with_email(test_app: &TestApp) {
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    users::set_email(test_app.pool(), "admin", Some(EMAIL))
+        .await
+        .unwrap();
+}
+
+async fn request_reset_token(test_app: &TestApp) -> String {
+    let response = test_app
+        .forgot_password(EMAIL)
+        .await
+        .expect("Failed to request reset");
+    assert_eq!("/password/forgot", response.headers()["Location"]);
+
+    test_app
+        .get_password_reset_tokens(EMAIL)
+        .await
+        .pop()
+        .expect("No reset email was sent")
+}
+
+#[tokio::test]
+async fn reset_link_sets_new_password_once() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    let token = request_reset_token(&test_app).await;
+
+    let response = test_app
+        .get_reset_password(&token)
+        .await
+        .expect("Failed to open reset link");
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains(&token));
+
+    let response = test_app
+        .reset_password(&token, NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to reset password");
+    assert_eq!("/login", response.headers()["Location"]);
+    let response = test_app
+        .login("admin", NEW_PASSWORD)
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+
+    let response = test_app
+        .reset_password(&token, "another long password", "another long password")
+        .await
+        .expect("Failed to reset password");
+    assert_eq!("/password/forgot", response.headers()["Location"]);
+    assert!(test_app
+        .get_forgot_password_html()
+        .await
+        .contains("invalid or has expired"));
+}
+
+#[tokio::test]
+async fn reset_ends_existing_sessions() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    let token = request_reset_token(&test_app).await;
+
+    test_app
+        .reset_password(&token, NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to reset password");
+
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn token_is_stored_hashed() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    let token = request_reset_token(&test_app).await;
+
+    let stored = sqlx::query!("SELECT token_hash FROM password_reset_tokens")
+        .fetch_all(test_app.pool())
+        .await
+        .unwrap();
+
+    assert_eq!(1, stored.len());
+    assert_ne!(token.as_bytes(), stored[0].token_hash.as_slice());
+}
+
+#[tokio::test]
+async fn expired_link_is_refused() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    let token = request_reset_token(&test_app).await;
+    test_app
+        .age_password_reset_tokens(Duration::from_secs(2 * 60 * 60))
+        .await;
+
+    let response = test_app
+        .get_reset_password(&token)
+        .await
+        .expect("Failed to open reset link");
+    assert_eq!("/password/forgot", response.headers()["Location"]);
+
+    let response = test_app
+        .reset_password(&token, NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to reset password");
+    assert_eq!("/password/forgot", response.headers()["Location"]);
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn weak_password_is_refused_and_token_kept() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    let token = request_reset_token(&test_app).await;
+
+    let response = test_app
+        .reset_password(&token, "short", "short")
+        .await
+        .expect("Failed to reset password");
+    assert_eq!(
+        format!("/password/reset?token={}", token),
+        response.headers()["Location"]
+    );
+
+    let response = test_app
+        .get_reset_password(&token)
+        .await
+        .expect("Failed to open reset link");
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("at least 12 characters"));
+
+    let response = test_app
+        .reset_password(&token, NEW_PASSWORD, NEW_PASSWORD)
+        .await
+        .expect("Failed to reset password");
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn unknown_and_disabled_addresses_get_the_same_answer_and_no_email() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    users::disable(test_app.pool(), "admin").await.unwrap();
+
+    for email in ["nobody@example.com", EMAIL] {
+        let response = test_app
+            .forgot_password(email)
+            .await
+            .expect("Failed to request reset");
+        assert_eq!("/password/forgot", response.headers()["Location"]);
+        assert!(test_app
+            .get_forgot_password_html()
+            .await
+            .contains("If this address belongs to an account"));
+    }
+
+    assert!(test_app.get_password_reset_tokens(EMAIL).await.is_empty());
+}
+
+#[tokio::test]
+async fn repeated_requests_are_rate_limited() {
+    let test_app = spawn().await.unwrap();
+    add_admin_with_email(&test_app).await;
+    let token = request_reset_token(&test_app).await;
+
+    test_app
+        .forgot_password(EMAIL)
+        .await
+        .expect("Failed to request reset");
+
+    assert_eq!(vec![token], test_app.get_password_reset_tokens(EMAIL).await);
+}
@@ -0,0 +1,74 @@
+//! tests/api/shutdown.rs
+
+use std::time::{Duration, Instant};
+
+use crate::test_app::{spawn, spawn_with, TestApp};
+
+/// Publishes an issue to a single subscriber, with the newsletter email
+/// taking `send_delay` to send.
+async fn publish_to_one_subscriber(test_app: &TestApp, send_delay: Duration) {
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app
+        .create_confirmed_subscriber("le guin".into(), "ursula_le_guin@gmail.com".into())
+        .await;
+    test_app.wait_for_outbox().await;
+    test_app.email_service().set_send_delay(send_delay);
+
+    let response = test_app
+        .publish_newsletter(
+            Some("<p>Newsletter body</p>".into()),
+            Some("Newsletter body".into()),
+            Some("Newsletter title".into()),
+            "admin",
+            Some("password"),
+        )
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(202, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_email_being_sent() {
+    let mut test_app = spawn().await.unwrap();
+    publish_to_one_subscriber(&test_app, Duration::from_millis(500)).await;
+    // The confirmation email, then the newsletter.
+    test_app.email_service().wait_for_sends_started(2).await;
+
+    test_app.shutdown().await.expect("Failed to shut down");
+
+    let sent = test_app.get_sent_emails();
+    assert_eq!(2, sent.len());
+    assert!(sent[1].1.starts_with("<p>Newsletter body</p>"));
+    assert_eq!(0, test_app.count_queued_deliveries().await);
+}
+
+#[tokio::test]
+async fn shutdown_cuts_off_sends_at_the_deadline() {
+    let mut test_app = spawn_with(|config| config.shutdown_timeout = Duration::from_secs(1))
+        .await
+        .unwrap();
+    publish_to_one_subscriber(&test_app, Duration::from_secs(30)).await;
+    test_app.email_service().wait_for_sends_started(2).await;
+
+    let started = Instant::now();
+    test_app.shutdown().await.expect("Failed to shut down");
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The unfinished delivery is left for the next instance to pick up.
+    assert_eq!(1, test_app.count_queued_deliveries().await);
+}
+
+#[tokio::test]
+async fn shutdown_stops_accepting_requests() {
+    let mut test_app = spawn().await.unwrap();
+
+    test_app.shutdown().await.expect("Failed to shut down");
+
+    let result = reqwest::Client::new()
+        .get(format!("{}/health_check", test_app.address()))
+        .send()
+        .await;
+    assert!(result.is_err());
+}
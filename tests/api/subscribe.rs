@@ -1,6 +1,9 @@
 use fake::{faker, Fake};
 
-use crate::test_app::spawn;
+use crate::test_app::{spawn, BASE_URL, RESEND_INTERVAL};
+use zero2prod::domain::subscriber::SubscriptionStatus;
+use zero2prod::domain::HashedToken;
+use zero2prod::email::EmailError;
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -19,15 +22,11 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
     let subscriber_id = test_app.get_subscription(&name, &email).await;
     let subscription_token = test_app.get_subscription_token(subscriber_id).await;
 
-    let expected_confirmation_link =
-        &format!("https://zero2prod.xyz/confirm?token={}", subscription_token);
+    let expected_confirmation_link = &format!("{}/confirm?token={}", BASE_URL, subscription_token);
 
     let sent_messages = test_app.get_sent_emails();
     assert_eq!(sent_messages.len(), 1);
-    assert_eq!(
-        sent_messages[0].1.contains(expected_confirmation_link),
-        true
-    );
+    assert!(sent_messages[0].1.contains(expected_confirmation_link));
 }
 
 #[tokio::test]
@@ -77,3 +76,134 @@ async fn subscribe_returns_a_400_when_data_is_missing() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribing_again_while_pending_resends_a_fresh_confirmation() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+
+    test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    let first_token = test_app.get_subscription_token(subscriber_id).await;
+
+    test_app
+        .age_subscription_tokens(subscriber_id, RESEND_INTERVAL * 2)
+        .await;
+
+    let response = test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let second_token = test_app.get_subscription_token(subscriber_id).await;
+    assert_ne!(first_token, second_token);
+    assert_eq!(2, test_app.get_sent_emails().len());
+
+    let response = test_app.confirm_subscription(&first_token).await.unwrap();
+    assert_eq!(400, response.status().as_u16(), "Old link must not work");
+    let response = test_app.confirm_subscription(&second_token).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn resending_confirmation_is_rate_limited_per_address() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+
+    test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+
+    let response = test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(429, response.status().as_u16());
+    test_app.wait_for_outbox().await;
+    assert_eq!(1, test_app.get_sent_emails().len());
+
+    let other_email: String = faker::internet::en::SafeEmail().fake();
+    let response = test_app
+        .create_subscription(name, other_email)
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_again_when_confirmed_sends_nothing() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(name.clone(), email.clone())
+        .await;
+
+    let response = test_app
+        .create_subscription(name, email.clone())
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+    test_app.wait_for_outbox().await;
+    assert_eq!(1, test_app.get_sent_emails().len());
+    assert_eq!(
+        SubscriptionStatus::Confirmed,
+        test_app.get_subscription_status(&email).await
+    );
+}
+
+#[tokio::test]
+async fn subscribe_stores_only_a_hash_of_the_token() {
+    let test_app = spawn().await.unwrap();
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+    test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    let token = test_app.get_subscription_token(subscriber_id).await;
+    assert_eq!(32, token.len());
+
+    let token = HashedToken::parse(token).unwrap();
+    assert_eq!(
+        vec![token.hash()],
+        test_app.get_subscription_token_hashes(subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_while_the_email_provider_is_failing() {
+    let test_app = spawn().await.unwrap();
+    test_app.email_service().fail_next(EmailError::Transport {
+        code: Some(421),
+        message: "Service not available".into(),
+    });
+
+    let name: String = faker::name::en::FirstName().fake();
+    let email: String = faker::internet::en::SafeEmail().fake();
+
+    let response = test_app
+        .create_subscription(name.clone(), email.clone())
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    // The relay retries until the confirmation goes out.
+    let subscriber_id = test_app.get_subscription(&name, &email).await;
+    let token = test_app.get_subscription_token(subscriber_id).await;
+    let response = test_app.confirm_subscription(&token).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,166 @@
+//! tests/api/subscribers.rs
+
+use std::collections::HashSet;
+
+use crate::test_app::{spawn, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::FirstName;
+use fake::Fake;
+
+async fn logged_in_app() -> TestApp {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    test_app.login("admin", "password").await.unwrap();
+    test_app
+}
+
+async fn subscriber_emails(test_app: &TestApp, query: &[(&str, &str)]) -> Vec<String> {
+    let response = test_app.get_subscribers_json(query).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let page: serde_json::Value = response.json().await.unwrap();
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_require_login() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app.get_subscribers_json(&[]).await;
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status() {
+    let test_app = logged_in_app().await;
+
+    let pending_email: String = SafeEmail().fake();
+    test_app
+        .create_subscription(FirstName().fake(), pending_email.clone())
+        .await
+        .unwrap();
+    let confirmed_email: String = SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), confirmed_email.clone())
+        .await;
+
+    assert_eq!(
+        vec![confirmed_email],
+        subscriber_emails(&test_app, &[("status", "confirmed")]).await
+    );
+    assert_eq!(
+        vec![pending_email],
+        subscriber_emails(&test_app, &[("status", "pending")]).await
+    );
+
+    let response = test_app.get_subscribers_json(&[("status", "bogus")]).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscriber_search_is_case_insensitive() {
+    let test_app = logged_in_app().await;
+
+    test_app
+        .create_subscription("Ursula".into(), "ursula@example.com".into())
+        .await
+        .unwrap();
+    test_app
+        .create_subscription("Bob".into(), "bob@example.com".into())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        vec!["ursula@example.com".to_string()],
+        subscriber_emails(&test_app, &[("q", "URSU")]).await
+    );
+    assert_eq!(
+        vec!["bob@example.com".to_string()],
+        subscriber_emails(&test_app, &[("q", "BOB@")]).await
+    );
+    assert!(subscriber_emails(&test_app, &[("q", "%")]).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_with_a_cursor() {
+    let test_app = logged_in_app().await;
+
+    for _ in 0..5 {
+        test_app
+            .create_subscription(FirstName().fake(), SafeEmail().fake())
+            .await
+            .unwrap();
+    }
+
+    let mut seen = HashSet::new();
+    let mut after: Option<String> = None;
+    let mut pages = 0;
+    loop {
+        let mut query = vec![("limit", "2")];
+        if let Some(ref cursor) = after {
+            query.push(("after", cursor));
+        }
+        let page: serde_json::Value = test_app
+            .get_subscribers_json(&query)
+            .await
+            .json()
+            .await
+            .unwrap();
+
+        for subscriber in page["subscribers"].as_array().unwrap() {
+            assert!(seen.insert(subscriber["id"].as_str().unwrap().to_string()));
+        }
+        pages += 1;
+
+        match page["next_cursor"].as_str() {
+            Some(cursor) => after = Some(cursor.to_string()),
+            None => break,
+        }
+    }
+
+    assert_eq!(5, seen.len());
+    assert_eq!(3, pages);
+}
+
+#[tokio::test]
+async fn subscribers_page_renders_html() {
+    let test_app = logged_in_app().await;
+    let email: String = SafeEmail().fake();
+    test_app
+        .create_subscription(FirstName().fake(), email.clone())
+        .await
+        .unwrap();
+
+    let response = test_app.get_subscribers_html(&[]).await;
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains(&email));
+}
+
+#[tokio::test]
+async fn filter_form_defaults_are_accepted() {
+    let test_app = logged_in_app().await;
+    let email: String = SafeEmail().fake();
+    test_app
+        .create_subscription(FirstName().fake(), email.clone())
+        .await
+        .unwrap();
+
+    let response = test_app
+        .get_subscribers_html(&[("q", ""), ("status", "")])
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains(&email));
+
+    let response = test_app
+        .get_subscribers_json(&[("status", ""), ("after", "")])
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
@@ -1,25 +1,41 @@
 //! tests/api/test_app.rs
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
     Argon2,
 };
-use reqwest::Response;
-use serde_json;
+use reqwest::{RequestBuilder, Response};
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{Pool, Postgres};
+use sqlx::{Connection, Executor, PgConnection, Pool, Postgres};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 use zero2prod::app::Application;
-use zero2prod::config::Config;
+use zero2prod::config::{Config, DatabaseConfig, Scope};
+use zero2prod::domain::subscriber::SubscriptionStatus;
+use zero2prod::migrations::{self, MigrationStatus};
 
 use crate::mocks::MockEmailService;
 
+/// Public URL the spawned application builds email links with.
+pub const BASE_URL: &str = "https://newsletter.test";
+pub const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+pub const RESEND_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct TestApp {
     address: String,
     pool: Pool<Postgres>,
     email_service: Arc<MockEmailService>,
+    /// Keeps cookies and does not follow redirects, like a browser session
+    /// whose redirects the tests want to inspect.
+    api_client: reqwest::Client,
+    /// Notified to shut the application down, like SIGTERM would.
+    shutdown: Arc<Notify>,
+    server: JoinHandle<Result<(), std::io::Error>>,
+    config: Config,
 }
 
 impl TestApp {
@@ -48,6 +64,17 @@ impl TestApp {
         .expect("Failed to create test user.");
     }
 
+    pub async fn get_password_hash(&self, username: &str) -> String {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE username = $1",
+            username
+        )
+        .fetch_one(&self.pool)
+        .await
+        .expect("Failed to fetch password hash")
+        .password_hash
+    }
+
     pub async fn get_subscription(
         &self,
         subscriber_name: &String,
@@ -65,16 +92,124 @@ impl TestApp {
         subscriber.id
     }
 
+    pub async fn get_subscription_status(&self, subscriber_email: &str) -> SubscriptionStatus {
+        sqlx::query!(
+            r#"SELECT status as "status: SubscriptionStatus" FROM subscriptions WHERE email = $1"#,
+            subscriber_email
+        )
+        .fetch_one(&self.pool)
+        .await
+        .expect("Failed to fetch subscription status")
+        .status
+    }
+
+    /// Overwrites the status column directly, bypassing the application.
+    pub async fn set_subscription_status(
+        &self,
+        subscriber_email: &str,
+        status: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE subscriptions SET status = $2::subscription_status WHERE email = $1")
+            .bind(subscriber_email)
+            .bind(status)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn get_status_history(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Vec<(Option<SubscriptionStatus>, SubscriptionStatus)> {
+        sqlx::query!(
+            r#"
+            SELECT
+                from_status as "from_status: SubscriptionStatus",
+                to_status as "to_status: SubscriptionStatus"
+            FROM subscription_status_history
+            WHERE subscriber_id = $1
+            ORDER BY id
+            "#,
+            subscriber_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to fetch status history")
+        .into_iter()
+        .map(|record| (record.from_status, record.to_status))
+        .collect()
+    }
+
+    /// Pretends the subscriber's confirmation tokens were created `age` ago.
+    pub async fn age_subscription_tokens(&self, subscriber_id: Uuid, age: Duration) {
+        sqlx::query!(
+            "UPDATE subscription_tokens SET created_at = $2 WHERE subscriber_id = $1",
+            subscriber_id,
+            chrono::Utc::now() - chrono::Duration::from_std(age).unwrap()
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to age subscription tokens");
+    }
+
+    pub async fn count_subscription_tokens(&self, subscriber_id: Uuid) -> i64 {
+        sqlx::query!(
+            r#"SELECT COUNT(*) as "count!" FROM subscription_tokens WHERE subscriber_id = $1"#,
+            subscriber_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .expect("Failed to count subscription tokens")
+        .count
+    }
+
+    pub async fn delete_expired_tokens(&self) -> u64 {
+        zero2prod::subscription_tokens::delete_expired_tokens(&self.pool, TOKEN_TTL)
+            .await
+            .expect("Failed to delete expired tokens")
+    }
+
+    /// Returns the token from the last confirmation email sent to the
+    /// subscriber; only its hash is stored in the database.
     pub async fn get_subscription_token(&self, subscriber_id: Uuid) -> String {
-        let subscription_token = sqlx::query!(
-            "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        let subscriber = sqlx::query!(
+            "SELECT email FROM subscriptions WHERE id = $1",
             subscriber_id
         )
         .fetch_one(&self.pool)
         .await
-        .expect("Failed to fetch subscription token");
+        .expect("Failed to fetch subscriber");
 
-        subscription_token.subscription_token
+        self.wait_for_outbox().await;
+        let (_, _, plaintext) = self
+            .get_sent_emails()
+            .into_iter()
+            .rev()
+            .find(|(to, _, plaintext)| {
+                to == &subscriber.email && plaintext.contains("/confirm?token=")
+            })
+            .expect("No confirmation email was sent");
+
+        let (_, token) = plaintext
+            .split_once("/confirm?token=")
+            .expect("Confirmation link is missing");
+        token
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect()
+    }
+
+    pub async fn get_subscription_token_hashes(&self, subscriber_id: Uuid) -> Vec<Vec<u8>> {
+        sqlx::query!(
+            "SELECT subscription_token_hash FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to fetch subscription tokens")
+        .into_iter()
+        .map(|record| record.subscription_token_hash)
+        .collect()
     }
 
     pub async fn get_confirmed_subscriptions(&self) -> usize {
@@ -92,6 +227,109 @@ impl TestApp {
         confirmed_count.count.expect("Error getting count") as usize
     }
 
+    pub async fn create_confirmed_subscriber(&self, name: String, email: String) {
+        self.create_subscription(name.clone(), email.clone())
+            .await
+            .expect("Failed to post subscription");
+
+        let subscriber_id = self.get_subscription(&name, &email).await;
+        let subscription_token = self.get_subscription_token(subscriber_id).await;
+
+        self.confirm_subscription(&subscription_token)
+            .await
+            .expect("Failed to confirm subscription");
+    }
+
+    /// Shuts the application down gracefully and waits until it has stopped.
+    pub async fn shutdown(&mut self) -> Result<(), std::io::Error> {
+        self.shutdown.notify_one();
+        (&mut self.server).await.expect("Application task panicked")
+    }
+
+    /// Waits for the application to stop on its own.
+    pub async fn stopped(&mut self) -> Result<(), std::io::Error> {
+        tokio::time::timeout(Duration::from_secs(10), &mut self.server)
+            .await
+            .expect("Application did not stop")
+            .expect("Application task panicked")
+    }
+
+    /// Starts another instance of the application on the same database, with
+    /// the configuration adjusted by `configure`.
+    pub async fn respawn_with(
+        &self,
+        configure: impl FnOnce(&mut Config),
+    ) -> Result<TestApp, String> {
+        let mut config = self.config.clone();
+        configure(&mut config);
+        start(config, self.pool.clone()).await
+    }
+
+    /// Number of deliveries still waiting in `issue_delivery_queue`.
+    pub async fn count_queued_deliveries(&self) -> i64 {
+        sqlx::query!("SELECT COUNT(*) as count FROM issue_delivery_queue")
+            .fetch_one(&self.pool)
+            .await
+            .expect("Failed to fetch pending delivery count")
+            .count
+            .unwrap_or_default()
+    }
+
+    /// Waits for the background workers to drain the delivery queue and
+    /// the email outbox.
+    pub async fn wait_for_deliveries(&self) {
+        for _ in 0..100 {
+            let pending = sqlx::query!("SELECT COUNT(*) as count FROM issue_delivery_queue")
+                .fetch_one(&self.pool)
+                .await
+                .expect("Failed to fetch pending delivery count");
+
+            if pending.count == Some(0) {
+                return self.wait_for_outbox().await;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Delivery queue was not drained in time");
+    }
+
+    /// Status, retry count and last error of every outbox email sent to
+    /// `recipient`.
+    pub async fn get_outbox_emails(&self, recipient: &str) -> Vec<(String, i32, Option<String>)> {
+        sqlx::query!(
+            r#"
+            SELECT status::TEXT as "status!", n_retries, last_error
+            FROM email_outbox
+            WHERE recipient = $1
+            ORDER BY created_at
+            "#,
+            recipient
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to fetch outbox emails")
+        .into_iter()
+        .map(|record| (record.status, record.n_retries, record.last_error))
+        .collect()
+    }
+
+    /// Waits for the relay to send every email in the outbox.
+    pub async fn wait_for_outbox(&self) {
+        for _ in 0..100 {
+            let pending = sqlx::query!(
+                "SELECT COUNT(*) as count FROM email_outbox WHERE status IN ('pending', 'retrying')"
+            )
+            .fetch_one(&self.pool)
+            .await
+            .expect("Failed to fetch outbox count");
+
+            if pending.count == Some(0) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Email outbox was not drained in time");
+    }
+
     pub async fn create_subscription(
         &self,
         name: String,
@@ -101,7 +339,7 @@ impl TestApp {
 
         let client = reqwest::Client::new();
         client
-            .post(&format!("{}/subscriptions", self.address()))
+            .post(format!("{}/subscriptions", self.address()))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -111,7 +349,7 @@ impl TestApp {
     pub async fn confirm_subscription(&self, token: &str) -> Result<Response, reqwest::Error> {
         let client = reqwest::Client::new();
         client
-            .get(&format!("{}/confirm?token={}", self.address(), token))
+            .get(format!("{}/confirm?token={}", self.address(), token))
             .send()
             .await
     }
@@ -124,6 +362,34 @@ impl TestApp {
         username: &str,
         password: Option<&str>,
     ) -> Result<Response, reqwest::Error> {
+        self.newsletter_request(html, text, subject, username, password)
+            .send()
+            .await
+    }
+
+    pub async fn publish_newsletter_with_idempotency_key(
+        &self,
+        html: Option<String>,
+        text: Option<String>,
+        subject: Option<String>,
+        username: &str,
+        password: Option<&str>,
+        idempotency_key: &str,
+    ) -> Result<Response, reqwest::Error> {
+        self.newsletter_request(html, text, subject, username, password)
+            .header("Idempotency-Key", idempotency_key)
+            .send()
+            .await
+    }
+
+    fn newsletter_request(
+        &self,
+        html: Option<String>,
+        text: Option<String>,
+        subject: Option<String>,
+        username: &str,
+        password: Option<&str>,
+    ) -> RequestBuilder {
         let mut newsletter = serde_json::Map::new();
         if let Some(html_value) = html {
             newsletter.insert("html".to_string(), serde_json::Value::String(html_value));
@@ -142,18 +408,290 @@ impl TestApp {
 
         let client = reqwest::Client::new();
         client
-            .post(&format!("{}/newsletter", self.address()))
+            .post(format!("{}/newsletter", self.address()))
             .basic_auth(username, password)
             .header("Content-Type", "application/json")
             .body(serde_json::json!(newsletter).to_string())
-            .send()
-            .await
     }
 
     pub async fn confirm_subscription_no_token(&self) -> Result<Response, reqwest::Error> {
         let client = reqwest::Client::new();
         client
-            .get(&format!("{}/confirm", self.address()))
+            .get(format!("{}/confirm", self.address()))
+            .send()
+            .await
+    }
+
+    pub async fn login(&self, username: &str, password: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/login", self.address()))
+            .form(&[("username", username), ("password", password)])
+            .send()
+            .await
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.get_html("/login").await
+    }
+
+    pub async fn get_newsletter_form_html(&self) -> String {
+        self.get_html("/admin/newsletter").await
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_html("/admin/password").await
+    }
+
+    pub async fn change_password(
+        &self,
+        current_password: &str,
+        new_password: &str,
+        new_password_check: &str,
+    ) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/password", self.address()))
+            .form(&[
+                ("current_password", current_password),
+                ("new_password", new_password),
+                ("new_password_check", new_password_check),
+            ])
+            .send()
+            .await
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.get_html("/password/forgot").await
+    }
+
+    pub async fn forgot_password(&self, email: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/password/forgot", self.address()))
+            .form(&[("email", email)])
+            .send()
+            .await
+    }
+
+    pub async fn get_reset_password(&self, token: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .get(format!("{}/password/reset", self.address()))
+            .query(&[("token", token)])
+            .send()
+            .await
+    }
+
+    pub async fn reset_password(
+        &self,
+        token: &str,
+        new_password: &str,
+        new_password_check: &str,
+    ) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/password/reset", self.address()))
+            .form(&[
+                ("token", token),
+                ("new_password", new_password),
+                ("new_password_check", new_password_check),
+            ])
+            .send()
+            .await
+    }
+
+    /// Tokens of the password reset links emailed to `email`, oldest first.
+    pub async fn get_password_reset_tokens(&self, email: &str) -> Vec<String> {
+        self.wait_for_outbox().await;
+        self.get_sent_emails()
+            .into_iter()
+            .filter(|(to, _, _)| to == email)
+            .filter_map(|(_, _, plaintext)| {
+                let (_, token) = plaintext.split_once("/password/reset?token=")?;
+                Some(
+                    token
+                        .chars()
+                        .take_while(char::is_ascii_alphanumeric)
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    pub async fn age_password_reset_tokens(&self, age: Duration) {
+        sqlx::query!(
+            "UPDATE password_reset_tokens SET created_at = $1",
+            chrono::Utc::now() - chrono::Duration::from_std(age).unwrap()
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to age password reset tokens");
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.get_html("/admin/two_factor").await
+    }
+
+    pub async fn enable_two_factor(&self, code: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/two_factor/enable", self.address()))
+            .form(&[("code", code)])
+            .send()
+            .await
+    }
+
+    pub async fn disable_two_factor(&self, password: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/two_factor/disable", self.address()))
+            .form(&[("password", password)])
+            .send()
+            .await
+    }
+
+    pub async fn get_login_two_factor(&self) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .get(format!("{}/login/two_factor", self.address()))
+            .send()
+            .await
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.get_html("/login/two_factor").await
+    }
+
+    pub async fn login_two_factor(&self, code: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/login/two_factor", self.address()))
+            .form(&[("code", code)])
+            .send()
+            .await
+    }
+
+    pub async fn get_recovery_code_hashes(&self, username: &str) -> Vec<Vec<u8>> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT code_hash
+            FROM totp_recovery_codes JOIN users ON users.id = totp_recovery_codes.user_id
+            WHERE username = $1
+            "#,
+            username
+        )
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to get recovery codes")
+    }
+
+    pub async fn get_totp_secret(&self, username: &str) -> Option<Vec<u8>> {
+        sqlx::query_scalar!(
+            "SELECT totp_secret FROM users WHERE username = $1",
+            username
+        )
+        .fetch_one(&self.pool)
+        .await
+        .expect("Failed to get TOTP secret")
+    }
+
+    /// Queues a delivery that the workers leave alone for an hour.
+    pub async fn queue_delivery_later(&self, newsletter_issue_id: &str, subscriber_email: &str) {
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, execute_after)
+            VALUES ($1, $2, now() + interval '1 hour')
+            "#,
+            Uuid::parse_str(newsletter_issue_id).unwrap(),
+            subscriber_email
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to queue delivery");
+    }
+
+    pub async fn end_two_factor_lockout(&self, username: &str) {
+        sqlx::query!(
+            "UPDATE users SET totp_locked_until = now() WHERE username = $1",
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .expect("Failed to end two-factor lockout");
+    }
+
+    async fn get_html(&self, path: &str) -> String {
+        self.api_client
+            .get(format!("{}{}", self.address(), path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .expect("Failed to read response body.")
+    }
+
+    pub async fn get_admin_dashboard(&self) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .get(format!("{}/admin/dashboard", self.address()))
+            .send()
+            .await
+    }
+
+    pub async fn logout(&self) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/logout", self.address()))
+            .send()
+            .await
+    }
+
+    pub async fn publish_newsletter_form(
+        &self,
+        html: &str,
+        text: &str,
+        subject: &str,
+        idempotency_key: &str,
+    ) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/newsletter", self.address()))
+            .form(&[
+                ("html", html),
+                ("text", text),
+                ("subject", subject),
+                ("idempotency_key", idempotency_key),
+            ])
+            .send()
+            .await
+    }
+
+    pub async fn get_subscribers_json(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(format!("{}/admin/api/subscribers", self.address()))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", self.address()))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dead_letters(&self) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .get(format!("{}/admin/dead_letters", self.address()))
+            .send()
+            .await
+    }
+
+    pub async fn replay_dead_letters(
+        &self,
+        newsletter_issue_id: &str,
+        subscriber_email: Option<&str>,
+    ) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/admin/dead_letters/replay", self.address()))
+            .json(&serde_json::json!({
+                "newsletter_issue_id": newsletter_issue_id,
+                "subscriber_email": subscriber_email,
+            }))
             .send()
             .await
     }
@@ -161,25 +699,121 @@ impl TestApp {
     pub fn get_sent_emails(&self) -> Vec<(String, String, String)> {
         self.email_service.sent_messages.lock().unwrap().to_vec()
     }
+
+    pub fn get_sent_headers(&self) -> Vec<Vec<(String, String)>> {
+        self.email_service.sent_headers.lock().unwrap().to_vec()
+    }
+
+    pub async fn get_unsubscribe(&self, token: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .get(format!("{}/unsubscribe", self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+    }
+
+    /// Sends the RFC 8058 one-click unsubscribe request a mail client would.
+    pub async fn post_unsubscribe(&self, token: &str) -> Result<Response, reqwest::Error> {
+        self.api_client
+            .post(format!("{}/unsubscribe", self.address))
+            .query(&[("token", token)])
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+    }
+
+    pub async fn migration_status(&self) -> Vec<MigrationStatus> {
+        migrations::status(&self.pool)
+            .await
+            .expect("Failed to read migration status")
+    }
+
+    pub async fn run_migrations(&self) {
+        migrations::run(&self.pool)
+            .await
+            .expect("Failed to run migrations");
+    }
+
+    pub async fn revert_migration(&self) -> Result<Option<i64>, String> {
+        migrations::revert(&self.pool).await
+    }
+
+    pub fn email_service(&self) -> &MockEmailService {
+        &self.email_service
+    }
+
+    /// A pool of its own on the application's database, for calling library
+    /// functions directly.
+    pub fn pool(&self) -> &Pool<Postgres> {
+        &self.pool
+    }
+}
+
+/// Creates a throwaway database for a single test and returns its name, so
+/// that the delivery workers of concurrently running tests never share a queue.
+async fn configure_database(db_config: &DatabaseConfig) -> Result<String, String> {
+    let database_name = Uuid::new_v4().to_string();
+
+    let mut connection = PgConnection::connect_with(&db_config.connect_options())
+        .await
+        .map_err(|e| format!("Error connecting to db: {}", e))?;
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, database_name).as_str())
+        .await
+        .map_err(|e| format!("Error creating test database: {}", e))?;
+
+    Ok(database_name)
 }
 
 pub async fn spawn() -> Result<TestApp, String> {
-    let config = Config::new();
+    spawn_with(|_| {}).await
+}
+
+/// Like [`spawn`], letting `configure` adjust the configuration first.
+pub async fn spawn_with(configure: impl FnOnce(&mut Config)) -> Result<TestApp, String> {
+    let mut config = Config::load(Scope::Server)?;
+    config.db_config.database_name = configure_database(&config.db_config).await?;
+    config.base_url = BASE_URL.into();
+    config.delivery_config.max_retries = 2;
+    config.delivery_config.retry_base_delay = Duration::from_millis(10);
+    config.session_config.secure_cookie = false;
+    config.confirmation_config.token_ttl = TOKEN_TTL;
+    config.confirmation_config.resend_interval = RESEND_INTERVAL;
+    configure(&mut config);
+
+    let pool = PgPoolOptions::new()
+        .connect_with(config.db_config.connect_options())
+        .await
+        .map_err(|e| format!("Error connecting to db: {}", e))?;
+
+    start(config, pool).await
+}
 
+/// Runs the application with `config`, `pool` being the tests' own pool on
+/// its database.
+async fn start(config: Config, pool: Pool<Postgres>) -> Result<TestApp, String> {
     let email_service = Arc::new(MockEmailService::new());
 
     let app = Application::build(&config, "127.0.0.1:0".into(), email_service.clone()).await?;
     let address = format!("http://127.0.0.1:{}", app.port());
-    let _ = tokio::spawn(app.run_until_stopped());
+    let shutdown = Arc::new(Notify::new());
+    let signal = shutdown.clone();
+    let server = tokio::spawn(app.run_until(async move { signal.notified().await }));
 
-    let pool = PgPoolOptions::new()
-        .connect(&config.db_config.url)
-        .await
-        .map_err(|e| format!("Error connecting to db: {}", e))?;
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .map_err(|e| format!("Error building client: {}", e))?;
 
     Ok(TestApp {
         address,
         pool,
         email_service,
+        api_client,
+        shutdown,
+        server,
+        config,
     })
 }
@@ -0,0 +1,446 @@
+//! tests/api/two_factor.rs
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use totp_rs::{Algorithm, Secret, TOTP};
+use zero2prod::config::PasswordHashConfig;
+use zero2prod::users;
+
+use crate::test_app::{spawn, TestApp};
+
+/// An authenticator app, set up with the secret shown on the enrollment page.
+struct Authenticator(TOTP);
+
+impl Authenticator {
+    /// The code shown `steps` time steps from now.
+    fn code(&self, steps: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        self.0.generate((now + steps * 30) as u64)
+    }
+}
+
+/// Waits for a new time step if the current one is about to end, so that a
+/// test's codes do not cross a step boundary while it runs.
+async fn wait_for_fresh_step() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let remaining = 30 - now % 30;
+    if remaining < 10 {
+        tokio::time::sleep(Duration::from_secs(remaining)).await;
+    }
+}
+
+async fn log_in_admin(test_app: &TestApp) {
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+/// Sets up an authenticator from the enrollment page.
+async fn scan_qr_code(test_app: &TestApp) -> Authenticator {
+    let html_page = test_app.get_two_factor_html().await;
+    assert!(html_page.contains("otpauth://totp/zero2prod:admin?secret="));
+    assert!(html_page.contains(r#"<img src="data:image/png;base64,"#));
+
+    let (_, secret) = html_page
+        .split_once(r#"<code id="secret">"#)
+        .expect("No secret on the page");
+    let secret = &secret[..secret.find('<').unwrap()];
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        Some("zero2prod".to_string()),
+        "admin".to_string(),
+    )
+    .unwrap();
+    Authenticator(totp)
+}
+
+/// Turns on the second factor for the logged in admin, using up the code of
+/// the previous step, and returns the authenticator and recovery codes.
+async fn enable_two_factor(test_app: &TestApp) -> (Authenticator, Vec<String>) {
+    let authenticator = scan_qr_code(test_app).await;
+
+    let response = test_app
+        .enable_two_factor(&authenticator.code(-1))
+        .await
+        .expect("Failed to enable two-factor authentication");
+    assert_eq!(200, response.status().as_u16());
+
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|code| code[..code.find('<').unwrap()].to_string())
+        .collect();
+    (authenticator, recovery_codes)
+}
+
+/// Logs out and enters the password again, which leads to the second step.
+async fn log_in_again(test_app: &TestApp) {
+    test_app.logout().await.expect("Failed to log out");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let authenticator = scan_qr_code(&test_app).await;
+
+    for code in ["12345", "not a code"] {
+        let response = test_app
+            .enable_two_factor(code)
+            .await
+            .expect("Failed to enable two-factor authentication");
+        assert_eq!("/admin/two_factor", response.headers()["Location"]);
+        let html_page = test_app.get_two_factor_html().await;
+        assert!(html_page.contains("<p><i>The code is incorrect</i></p>"));
+        assert!(html_page.contains("Two-factor authentication is off"));
+    }
+
+    let response = test_app
+        .enable_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enable two-factor authentication");
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert_eq!(10, html_page.matches("<li><code>").count());
+
+    let html_page = test_app.get_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is on"));
+}
+
+#[tokio::test]
+async fn recovery_codes_are_stored_hashed() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+
+    let (_, recovery_codes) = enable_two_factor(&test_app).await;
+
+    let hashes = test_app.get_recovery_code_hashes("admin").await;
+    assert_eq!(10, recovery_codes.len());
+    assert_eq!(10, hashes.len());
+    for code in &recovery_codes {
+        assert!(!hashes.contains(&code.as_bytes().to_vec()));
+    }
+}
+
+#[tokio::test]
+async fn secrets_are_stored_encrypted() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+
+    let stored = test_app
+        .get_totp_secret("admin")
+        .await
+        .expect("No TOTP secret stored");
+    let secret = &authenticator.0.secret;
+    let encoded = authenticator.0.get_secret_base32();
+    assert!(!stored.windows(secret.len()).any(|window| window == secret));
+    assert!(!stored
+        .windows(encoded.len())
+        .any(|window| window == encoded.as_bytes()));
+}
+
+#[tokio::test]
+async fn login_requires_the_second_factor_when_enabled() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+
+    log_in_again(&test_app).await;
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+
+    let response = test_app
+        .login_two_factor("000000x")
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+    let html_page = test_app.get_login_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The code is incorrect</i></p>"));
+
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn codes_are_accepted_once() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+
+    // The code used to turn the second factor on is spent already.
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&authenticator.code(-1))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn recovery_codes_are_accepted_once() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (_, recovery_codes) = enable_two_factor(&test_app).await;
+
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&recovery_codes[0].to_uppercase())
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+    assert_eq!(9, test_app.get_recovery_code_hashes("admin").await.len());
+
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&recovery_codes[0])
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+    let response = test_app
+        .login_two_factor(&recovery_codes[1])
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_restart_the_login() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+    log_in_again(&test_app).await;
+
+    for _ in 0..4 {
+        let response = test_app
+            .login_two_factor("wrong")
+            .await
+            .expect("Failed to enter code");
+        assert_eq!("/login/two_factor", response.headers()["Location"]);
+    }
+    let response = test_app
+        .login_two_factor("wrong")
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Too many wrong codes"));
+
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+
+    // Entering the password again does not lift the lockout, even for the
+    // right code.
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Too many wrong codes"));
+
+    test_app.end_two_factor_lockout("admin").await;
+    log_in_again(&test_app).await;
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn disabling_the_user_ends_a_pending_login() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+    log_in_again(&test_app).await;
+
+    users::disable(test_app.pool(), "admin").await.unwrap();
+
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn changing_the_password_ends_a_pending_login() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    let (authenticator, _) = enable_two_factor(&test_app).await;
+    log_in_again(&test_app).await;
+
+    users::set_password(
+        test_app.pool(),
+        "admin",
+        &secrecy::Secret::new("new-password".to_string()),
+        &PasswordHashConfig {
+            memory_kib: 8192,
+            iterations: 1,
+            parallelism: 1,
+        },
+    )
+    .await
+    .unwrap();
+
+    let response = test_app
+        .login_two_factor(&authenticator.code(0))
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn second_step_requires_the_password_first() {
+    let test_app = spawn().await.unwrap();
+
+    let response = test_app.get_login_two_factor().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+
+    let response = test_app
+        .login_two_factor("123456")
+        .await
+        .expect("Failed to enter code");
+    assert_eq!("/login", response.headers()["Location"]);
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn basic_auth_is_refused_for_users_with_a_second_factor() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    enable_two_factor(&test_app).await;
+
+    let response = test_app
+        .publish_newsletter(
+            Some("<p>Newsletter body</p>".into()),
+            Some("Newsletter body".into()),
+            Some("Newsletter title".into()),
+            "admin",
+            Some("password"),
+        )
+        .await
+        .expect("Failed to publish newsletter");
+
+    assert_eq!(403, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    assert!(body.contains("two-factor authentication"));
+}
+
+#[tokio::test]
+async fn disabling_requires_the_password() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    enable_two_factor(&test_app).await;
+
+    let response = test_app
+        .disable_two_factor("wrong-password")
+        .await
+        .expect("Failed to disable two-factor authentication");
+    assert_eq!("/admin/two_factor", response.headers()["Location"]);
+    let html_page = test_app.get_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The password is incorrect</i></p>"));
+    assert!(html_page.contains("Two-factor authentication is on"));
+
+    test_app
+        .disable_two_factor("password")
+        .await
+        .expect("Failed to disable two-factor authentication");
+    let html_page = test_app.get_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is off"));
+    assert!(test_app.get_recovery_code_hashes("admin").await.is_empty());
+
+    test_app.logout().await.expect("Failed to log out");
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn a_pending_login_replaces_the_previous_user() {
+    let test_app = spawn().await.unwrap();
+    log_in_admin(&test_app).await;
+    wait_for_fresh_step().await;
+    enable_two_factor(&test_app).await;
+    test_app.logout().await.expect("Failed to log out");
+
+    test_app
+        .add_test_user("editor".to_string(), "editor password".to_string())
+        .await;
+    let response = test_app
+        .login("editor", "editor password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/admin/dashboard", response.headers()["Location"]);
+
+    let response = test_app
+        .login("admin", "password")
+        .await
+        .expect("Failed to log in");
+    assert_eq!("/login/two_factor", response.headers()["Location"]);
+    let response = test_app.get_admin_dashboard().await.unwrap();
+    assert_eq!("/login", response.headers()["Location"]);
+}
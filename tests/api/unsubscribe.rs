@@ -0,0 +1,167 @@
+//! tests/api/unsubscribe.rs
+
+use crate::test_app::{spawn, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::lorem::en::{Paragraph, Sentence};
+use fake::faker::name::en::FirstName;
+use fake::Fake;
+use reqwest::Url;
+use zero2prod::domain::subscriber::SubscriptionStatus;
+
+/// Publishes an issue and waits until it has been delivered, returning its
+/// HTML body so the sent emails can be told apart from other ones.
+async fn publish_and_deliver(test_app: &TestApp) -> String {
+    let text: String = Paragraph(1..2).fake();
+    let html = format!("<p>{}</p>", text);
+    let subject: String = Sentence(1..2).fake();
+
+    let response = test_app
+        .publish_newsletter(
+            Some(html.clone()),
+            Some(text),
+            Some(subject),
+            "admin",
+            Some("password"),
+        )
+        .await
+        .expect("Failed to publish newsletter");
+    assert_eq!(202, response.status().as_u16());
+
+    test_app.wait_for_deliveries().await;
+    html
+}
+
+/// Returns the `List-Unsubscribe` link the newsletter issue `html` was sent
+/// to `email` with.
+fn unsubscribe_link(test_app: &TestApp, email: &str, html: &str) -> String {
+    let (index, _) = test_app
+        .get_sent_emails()
+        .into_iter()
+        .enumerate()
+        .find(|(_, (to, email_html, _))| to == email && email_html.starts_with(html))
+        .expect("Newsletter was not sent");
+
+    let (_, list_unsubscribe) = test_app.get_sent_headers()[index]
+        .iter()
+        .find(|(name, _)| name == "List-Unsubscribe")
+        .cloned()
+        .expect("Missing List-Unsubscribe header");
+
+    list_unsubscribe
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
+
+fn token(link: &str) -> String {
+    let url = Url::parse(link).expect("Invalid unsubscribe link");
+    url.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .expect("Unsubscribe link has no token")
+}
+
+async fn setup() -> (TestApp, String) {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+
+    let email: String = SafeEmail().fake();
+    test_app
+        .create_confirmed_subscriber(FirstName().fake(), email.clone())
+        .await;
+
+    (test_app, email)
+}
+
+#[tokio::test]
+async fn newsletter_carries_one_click_unsubscribe_headers_and_link() {
+    let (test_app, email) = setup().await;
+
+    let html = publish_and_deliver(&test_app).await;
+
+    let link = unsubscribe_link(&test_app, &email, &html);
+    assert!(link.contains("/unsubscribe?token="));
+
+    let sent_emails = test_app.get_sent_emails();
+    let index = sent_emails
+        .iter()
+        .position(|(to, email_html, _)| to == &email && email_html.starts_with(&html))
+        .unwrap();
+    let (_, email_html, email_plaintext) = &sent_emails[index];
+    assert!(email_html.contains(&link));
+    assert!(email_plaintext.contains(&link));
+
+    assert!(test_app.get_sent_headers()[index].contains(&(
+        "List-Unsubscribe-Post".to_string(),
+        "List-Unsubscribe=One-Click".to_string()
+    )));
+}
+
+#[tokio::test]
+async fn unsubscribe_link_shows_a_confirmation_page() {
+    let (test_app, email) = setup().await;
+    let html = publish_and_deliver(&test_app).await;
+    let token = token(&unsubscribe_link(&test_app, &email, &html));
+
+    let response = test_app
+        .get_unsubscribe(&token)
+        .await
+        .expect("Failed to open unsubscribe page");
+    assert_eq!(200, response.status().as_u16());
+
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"method="post""#));
+
+    let status = test_app.get_subscription_status(&email).await;
+    assert_eq!(
+        SubscriptionStatus::Confirmed,
+        status,
+        "Opening the link must not unsubscribe"
+    );
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_stops_further_issues() {
+    let (test_app, email) = setup().await;
+    let html = publish_and_deliver(&test_app).await;
+    let token = token(&unsubscribe_link(&test_app, &email, &html));
+
+    for _ in 0..2 {
+        let response = test_app
+            .post_unsubscribe(&token)
+            .await
+            .expect("Failed to unsubscribe");
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let status = test_app.get_subscription_status(&email).await;
+    assert_eq!(SubscriptionStatus::Unsubscribed, status);
+
+    let html = publish_and_deliver(&test_app).await;
+    let newsletters_sent = test_app
+        .get_sent_emails()
+        .iter()
+        .filter(|(to, email_html, _)| to == &email && email_html.starts_with(&html))
+        .count();
+    assert_eq!(0, newsletters_sent);
+}
+
+#[tokio::test]
+async fn tampered_unsubscribe_token_returns_400() {
+    let (test_app, email) = setup().await;
+    let html = publish_and_deliver(&test_app).await;
+    let token = token(&unsubscribe_link(&test_app, &email, &html));
+
+    let (_, signature) = token.split_once('.').unwrap();
+    let forged = format!("{}.{}", uuid::Uuid::new_v4(), signature);
+
+    for token in [forged.as_str(), "not-a-token"] {
+        let response = test_app.get_unsubscribe(token).await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+
+        let response = test_app.post_unsubscribe(token).await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+    }
+}
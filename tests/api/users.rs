@@ -0,0 +1,209 @@
+//! tests/api/users.rs
+
+use secrecy::Secret;
+use zero2prod::config::PasswordHashConfig;
+use zero2prod::users;
+
+use crate::test_app::{spawn, TestApp};
+
+fn password_hash_config() -> PasswordHashConfig {
+    PasswordHashConfig {
+        memory_kib: 8192,
+        iterations: 1,
+        parallelism: 1,
+    }
+}
+
+fn password(password: &str) -> Secret<String> {
+    Secret::new(password.to_string())
+}
+
+async fn assert_logged_in(test_app: &TestApp) {
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!(200, response.status().as_u16());
+}
+
+async fn assert_logged_out(test_app: &TestApp) {
+    let response = test_app
+        .get_admin_dashboard()
+        .await
+        .expect("Failed to get dashboard");
+    assert_eq!("/login", response.headers()["Location"]);
+}
+
+async fn assert_login(test_app: &TestApp, username: &str, password: &str, location: &str) {
+    let response = test_app
+        .login(username, password)
+        .await
+        .expect("Failed to log in");
+    assert_eq!(location, response.headers()["Location"]);
+}
+
+#[tokio::test]
+async fn created_user_can_log_in() {
+    let test_app = spawn().await.unwrap();
+
+    users::create(
+        test_app.pool(),
+        "editor",
+        None,
+        &password("s3cret-passw0rd"),
+        &password_hash_config(),
+    )
+    .await
+    .unwrap();
+
+    assert_login(&test_app, "editor", "s3cret-passw0rd", "/admin/dashboard").await;
+    let users = users::list(test_app.pool()).await.unwrap();
+    assert_eq!(1, users.len());
+    assert_eq!("editor", users[0].username);
+    assert_eq!(None, users[0].disabled_at);
+}
+
+#[tokio::test]
+async fn creating_existing_user_fails() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("editor".to_string(), "password".to_string())
+        .await;
+
+    let error = users::create(
+        test_app.pool(),
+        "editor",
+        None,
+        &password("another-password"),
+        &password_hash_config(),
+    )
+    .await
+    .unwrap_err();
+
+    assert!(error.contains("already exists"), "{}", error);
+    assert_login(&test_app, "editor", "password", "/admin/dashboard").await;
+}
+
+#[tokio::test]
+async fn empty_username_or_password_is_rejected() {
+    let test_app = spawn().await.unwrap();
+
+    assert!(users::create(
+        test_app.pool(),
+        " ",
+        None,
+        &password("password"),
+        &password_hash_config()
+    )
+    .await
+    .is_err());
+    assert!(users::create(
+        test_app.pool(),
+        "editor",
+        None,
+        &password(""),
+        &password_hash_config()
+    )
+    .await
+    .is_err());
+    assert!(users::list(test_app.pool()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn set_password_replaces_password_and_ends_sessions() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("editor".to_string(), "password".to_string())
+        .await;
+    assert_login(&test_app, "editor", "password", "/admin/dashboard").await;
+    assert_logged_in(&test_app).await;
+
+    users::set_password(
+        test_app.pool(),
+        "editor",
+        &password("new-password"),
+        &password_hash_config(),
+    )
+    .await
+    .unwrap();
+
+    assert_logged_out(&test_app).await;
+    assert_login(&test_app, "editor", "password", "/login").await;
+    assert_login(&test_app, "editor", "new-password", "/admin/dashboard").await;
+}
+
+#[tokio::test]
+async fn disabled_user_cannot_log_in() {
+    let test_app = spawn().await.unwrap();
+    test_app
+        .add_test_user("editor".to_string(), "password".to_string())
+        .await;
+    assert_login(&test_app, "editor", "password", "/admin/dashboard").await;
+
+    users::disable(test_app.pool(), "editor").await.unwrap();
+
+    assert_logged_out(&test_app).await;
+    assert_login(&test_app, "editor", "password", "/login").await;
+    let users = users::list(test_app.pool()).await.unwrap();
+    assert!(users[0].disabled_at.is_some());
+}
+
+#[tokio::test]
+async fn unknown_users_cannot_be_changed() {
+    let test_app = spawn().await.unwrap();
+
+    let error = users::disable(test_app.pool(), "nobody").await.unwrap_err();
+    assert!(error.contains("nobody"), "{}", error);
+
+    let error = users::set_password(
+        test_app.pool(),
+        "nobody",
+        &password("password"),
+        &password_hash_config(),
+    )
+    .await
+    .unwrap_err();
+    assert!(error.contains("nobody"), "{}", error);
+}
+
+#[tokio::test]
+async fn email_address_can_be_set_and_removed() {
+    let test_app = spawn().await.unwrap();
+    users::create(
+        test_app.pool(),
+        "editor",
+        Some("editor@example.com"),
+        &password("password"),
+        &password_hash_config(),
+    )
+    .await
+    .unwrap();
+    test_app
+        .add_test_user("admin".to_string(), "password".to_string())
+        .await;
+
+    let error = users::set_email(test_app.pool(), "admin", Some("EDITOR@example.com"))
+        .await
+        .unwrap_err();
+    assert!(error.contains("another user"), "{}", error);
+    assert!(
+        users::set_email(test_app.pool(), "admin", Some("not an address"))
+            .await
+            .is_err()
+    );
+
+    users::set_email(test_app.pool(), "editor", None)
+        .await
+        .unwrap();
+    users::set_email(test_app.pool(), "admin", Some("admin@example.com"))
+        .await
+        .unwrap();
+
+    let emails: Vec<_> = users::list(test_app.pool())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| user.email)
+        .collect();
+    assert_eq!(vec![Some("admin@example.com".to_string()), None], emails);
+}